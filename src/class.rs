@@ -0,0 +1,178 @@
+use crate::collect::{self, Teardown, Trace, Unlink};
+use crate::function::Function;
+use crate::heap::{self, Allocation};
+use crate::parser::Type;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Rc<Function>>,
+}
+
+impl Class {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<String, Rc<Function>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    // methods not defined on the class itself are looked up the superclass chain
+    pub fn find_method(&self, name: &str) -> Option<Rc<Function>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+
+    // constructing an instance takes the initializer's arguments
+    pub fn arity(&self) -> usize {
+        match self.find_method("init") {
+            Some(initializer) => initializer.arity(),
+            None => 0,
+        }
+    }
+}
+
+impl Unlink for Class {
+    fn unlink(&mut self, teardown: &mut Teardown) {
+        teardown
+            .values
+            .extend(self.superclass.take().map(Type::Class));
+        let methods = mem::take(&mut self.methods);
+        teardown
+            .values
+            .extend(methods.into_values().map(Type::Function));
+    }
+}
+
+impl Drop for Class {
+    fn drop(&mut self) {
+        let mut teardown = Teardown::default();
+        self.unlink(&mut teardown);
+        teardown.run();
+    }
+}
+
+impl Trace for Class {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        if let Some(superclass) = &self.superclass {
+            visit(collect::address(superclass));
+        }
+        self.methods
+            .values()
+            .for_each(|method| visit(collect::address(method)));
+        true
+    }
+
+    // classes never change, so cycles through them are broken at their scopes
+    fn clear(&self) {}
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: HashMap<String, Type>,
+    allocation: Allocation,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+            allocation: Allocation::new(mem::size_of::<Instance>()),
+        }
+    }
+
+    // fields shadow methods, which are bound to the instance on access
+    pub fn get(instance: &Rc<RefCell<Instance>>, name: &str) -> Option<Type> {
+        if let Some(value) = instance.borrow().fields.get(name) {
+            return Some(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(name)?;
+        let bound = method.bind(Type::Instance(Rc::clone(instance)));
+        Some(Type::Function(heap::track(bound)))
+    }
+
+    pub fn set(&mut self, name: String, value: Type) {
+        let size = heap::slot_size(&name);
+        if self.fields.insert(name, value).is_none() {
+            self.allocation.grow(size);
+        }
+    }
+}
+
+impl Unlink for Instance {
+    // the class is queued rather than taken, and stays live until the instance
+    // itself is gone
+    fn unlink(&mut self, teardown: &mut Teardown) {
+        teardown
+            .values
+            .extend(mem::take(&mut self.fields).into_values());
+        teardown.values.push(Type::Class(Rc::clone(&self.class)));
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        let mut teardown = Teardown::default();
+        self.unlink(&mut teardown);
+        teardown.run();
+    }
+}
+
+impl Trace for RefCell<Instance> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(instance) = self.try_borrow() else {
+            return false;
+        };
+        visit(collect::address(&instance.class));
+        instance
+            .fields
+            .values()
+            .filter_map(collect::value_address)
+            .for_each(visit);
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut instance) = self.try_borrow_mut() {
+            let fields = mem::take(&mut instance.fields);
+            drop(instance);
+            drop(fields);
+        }
+    }
+}
+
+// fields are skipped as they may refer back to the instance
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instance")
+            .field("class", &self.class.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
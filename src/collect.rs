@@ -0,0 +1,148 @@
+use crate::environment::Environment;
+use crate::heap::Heap;
+use crate::parser::Type;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+// the objects script values can form cycles through: scopes, functions, classes
+// and instances
+pub trait Trace {
+    // calls `visit` with the address of each object this one holds a reference
+    // to; false if it is borrowed and so can't be looked into
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool;
+
+    // drops the references this object holds that can close a cycle
+    fn clear(&self);
+}
+
+pub fn address<T: ?Sized>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
+
+// the traced object a value refers to, if any
+pub fn value_address(value: &Type) -> Option<usize> {
+    match value {
+        Type::Function(function) => Some(address(function)),
+        Type::Class(class) => Some(address(class)),
+        Type::Instance(instance) => Some(address(instance)),
+        _ => None,
+    }
+}
+
+// frees the unreachable cycles among the objects `heap` tracks, returning how
+// many objects and references it looked at. there is no list of roots: an
+// object referred to more often than the tracked objects account for is held
+// from outside them, by the interpreter's globals, a scope being run or a value
+// part way through being evaluated, and everything it reaches is live
+pub fn collect(heap: &Heap) -> usize {
+    let objects: Vec<Rc<dyn Trace>> = heap
+        .take_tracked()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    let index: HashMap<usize, usize> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, object)| (address(object), i))
+        .collect();
+
+    // references from outside, less the one `objects` itself holds
+    let mut outside: Vec<usize> = objects
+        .iter()
+        .map(|object| Rc::strong_count(object) - 1)
+        .collect();
+    let mut edges: Vec<Vec<usize>> = Vec::with_capacity(objects.len());
+    let mut work = objects.len();
+    for (i, object) in objects.iter().enumerate() {
+        let mut targets = Vec::new();
+        let traced = object.trace(&mut |target| {
+            if let Some(&target) = index.get(&target) {
+                targets.push(target);
+            }
+        });
+        // an object that can't be looked into is kept, with all it refers to
+        if !traced {
+            outside[i] += 1;
+        }
+        for &target in &targets {
+            outside[target] -= 1;
+        }
+        work += targets.len();
+        edges.push(targets);
+    }
+
+    let mut live: Vec<bool> = outside.iter().map(|&count| count > 0).collect();
+    let mut pending: Vec<usize> = (0..objects.len()).filter(|&i| live[i]).collect();
+    while let Some(i) = pending.pop() {
+        for &target in &edges[i] {
+            if !live[target] {
+                live[target] = true;
+                pending.push(target);
+            }
+        }
+    }
+
+    // emptying the unreachable scopes and instances breaks every cycle among
+    // them, so they are all dropped along with `objects`
+    let mut survivors = Vec::new();
+    for (object, live) in objects.iter().zip(live) {
+        match live {
+            true => survivors.push(Rc::downgrade(object)),
+            false => object.clear(),
+        }
+    }
+    heap.retrack(survivors);
+
+    work
+}
+
+// objects whose last reference is being dropped, taken apart one at a time
+// rather than recursively, so a long chain of instances, scopes or classes
+// can't overflow the stack
+#[derive(Default)]
+pub struct Teardown {
+    pub values: Vec<Type>,
+    pub scopes: Vec<Rc<RefCell<Environment>>>,
+}
+
+// moves what an object holds into a teardown, leaving it cheap to drop
+pub trait Unlink {
+    fn unlink(&mut self, teardown: &mut Teardown);
+}
+
+impl Teardown {
+    // drops everything queued, unlinking each object nothing else refers to
+    // before it goes
+    pub fn run(mut self) {
+        loop {
+            if let Some(scope) = self.scopes.pop() {
+                if let Ok(scope) = Rc::try_unwrap(scope) {
+                    scope.into_inner().unlink(&mut self);
+                }
+                continue;
+            }
+            let Some(value) = self.values.pop() else {
+                return;
+            };
+            match value {
+                Type::Instance(instance) => {
+                    if let Ok(instance) = Rc::try_unwrap(instance) {
+                        instance.into_inner().unlink(&mut self);
+                    }
+                }
+                Type::Class(class) => {
+                    if let Ok(mut class) = Rc::try_unwrap(class) {
+                        class.unlink(&mut self);
+                    }
+                }
+                Type::Function(function) => {
+                    if let Ok(function) = Rc::try_unwrap(function) {
+                        self.scopes.push(function.closure);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
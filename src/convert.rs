@@ -0,0 +1,155 @@
+use crate::native::type_name;
+use crate::parser::Type;
+
+// conversion of a script value into a Rust value, failing with a message such as
+// "expected a number, got string"
+pub trait FromLox: Sized {
+    fn from_lox(value: &Type) -> Result<Self, String>;
+}
+
+pub trait IntoLox {
+    fn into_lox(self) -> Type;
+}
+
+fn mismatch(expected: &str, got: &Type) -> String {
+    format!("expected {}, got {}", expected, type_name(got))
+}
+
+impl FromLox for Type {
+    fn from_lox(value: &Type) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoLox for Type {
+    fn into_lox(self) -> Type {
+        self
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Type {
+        Type::Nil
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: &Type) -> Result<Self, String> {
+        match value {
+            Type::Number(value) => Ok(*value),
+            other => Err(mismatch("a number", other)),
+        }
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Type {
+        Type::Number(self)
+    }
+}
+
+impl FromLox for f32 {
+    fn from_lox(value: &Type) -> Result<Self, String> {
+        f64::from_lox(value).map(|value| value as f32)
+    }
+}
+
+impl IntoLox for f32 {
+    fn into_lox(self) -> Type {
+        Type::Number(self as f64)
+    }
+}
+
+// numbers are only accepted as integers when whole and within the target's range
+macro_rules! integer_conversions {
+    ($($int:ty),*) => {
+        $(
+            impl FromLox for $int {
+                fn from_lox(value: &Type) -> Result<Self, String> {
+                    let number = f64::from_lox(value)
+                        .map_err(|_| mismatch("an integer", value))?;
+
+                    if number.fract() != 0. {
+                        return Err(format!("expected an integer, got {}", number));
+                    }
+                    // `MAX as f64` rounds up for 64-bit types, so compare against the
+                    // exact power of two just past it instead
+                    let bound = (<$int>::MAX / 2 + 1) as f64 * 2.;
+                    if number < <$int>::MIN as f64 || number >= bound {
+                        return Err(format!(
+                            "expected an integer in {}..={}, got {}",
+                            <$int>::MIN,
+                            <$int>::MAX,
+                            number
+                        ));
+                    }
+
+                    Ok(number as $int)
+                }
+            }
+
+            impl IntoLox for $int {
+                fn into_lox(self) -> Type {
+                    Type::Number(self as f64)
+                }
+            }
+        )*
+    };
+}
+
+integer_conversions!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl FromLox for bool {
+    fn from_lox(value: &Type) -> Result<Self, String> {
+        match value {
+            Type::Bool(value) => Ok(*value),
+            other => Err(mismatch("a bool", other)),
+        }
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Type {
+        Type::Bool(self)
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: &Type) -> Result<Self, String> {
+        match value {
+            Type::String(value) => Ok(value.to_string()),
+            other => Err(mismatch("a string", other)),
+        }
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Type {
+        Type::String(self.into())
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Type {
+        Type::String(self.into())
+    }
+}
+
+// nil maps to None, anything else must convert to the inner type
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: &Type) -> Result<Self, String> {
+        match value {
+            Type::Nil => Ok(None),
+            other => T::from_lox(other).map(Some),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Type {
+        match self {
+            Some(value) => value.into_lox(),
+            None => Type::Nil,
+        }
+    }
+}
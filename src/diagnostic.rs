@@ -0,0 +1,232 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Lexer,
+    Parser,
+    Resolver,
+    Runtime,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Phase::Lexer => "LEXER",
+            Phase::Parser => "PARSER",
+            Phase::Resolver => "RESOLVER",
+            Phase::Runtime => "RUNTIME",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARNING",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// a byte range into the source, with the line and column of its start; `source`
+// tells apart the separate pieces of code evaluated by one session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub source: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+            source: 0,
+        }
+    }
+
+    pub fn with_source(mut self, source: usize) -> Span {
+        self.source = source;
+        self
+    }
+
+    // the smallest span covering both
+    pub fn to(self, other: Span) -> Span {
+        let (first, last) = match self.start <= other.start {
+            true => (self, other),
+            false => (other, self),
+        };
+
+        Span::new(
+            first.start,
+            first.end.max(last.end),
+            first.line,
+            first.column,
+        )
+        .with_source(first.source)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}:{}]", self.line, self.column)
+    }
+}
+
+// a secondary location that helps explain a diagnostic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// a function call that was active when a runtime error was raised
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    // where the function was called from
+    pub call_site: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub phase: Phase,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    // innermost call first; empty outside runtime errors
+    pub trace: Vec<StackFrame>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(phase: Phase, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            phase,
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            trace: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_trace(mut self, trace: Vec<StackFrame>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn to_json(&self) -> String {
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|label| {
+                format!(
+                    "{{\"message\":{},\"start\":{},\"end\":{},\"line\":{},\"column\":{}}}",
+                    json_string(&label.message),
+                    label.span.start,
+                    label.span.end,
+                    label.span.line,
+                    label.span.column
+                )
+            })
+            .collect();
+        let trace: Vec<String> = self
+            .trace
+            .iter()
+            .map(|frame| {
+                format!(
+                    "{{\"function\":{},\"line\":{},\"column\":{}}}",
+                    json_string(&frame.function),
+                    frame.call_site.line,
+                    frame.call_site.column
+                )
+            })
+            .collect();
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+
+        format!(
+            "{{\"phase\":{},\"severity\":{},\"message\":{},\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"labels\":[{}],\"trace\":[{}],\"notes\":[{}]}}",
+            json_string(&format!("{:?}", self.phase).to_lowercase()),
+            json_string(&format!("{:?}", self.severity).to_lowercase()),
+            json_string(&self.message),
+            self.span.start,
+            self.span.end,
+            self.span.line,
+            self.span.column,
+            labels.join(","),
+            trace.join(","),
+            notes.join(",")
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}: {}",
+            self.severity, self.phase, self.span, self.message
+        )?;
+        for frame in &self.trace {
+            write!(
+                f,
+                "\n  in {}() called at {}",
+                frame.function, frame.call_site
+            )?;
+        }
+        for note in &self.notes {
+            write!(f, "\n  note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -0,0 +1,304 @@
+use crate::convert::IntoLox;
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::{Abort, Failure, Interpreter};
+use crate::interrupt::InterruptHandle;
+use crate::native::{Args, HostFunction, NativeFunction};
+use crate::output::{self, Sink};
+use crate::parser::{self, Parser, Type};
+use crate::render::Renderer;
+use crate::resolver::Resolver;
+use crate::stack;
+use crate::tokenizer::Scanner;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub type Value = Type;
+
+#[derive(Debug)]
+pub enum LoxError {
+    Io(io::Error),
+    // scanner, parser or resolver errors, reported before anything runs
+    Compile(Vec<Diagnostic>),
+    // boxed since a stack trace makes it much larger than the other variants
+    Runtime(Box<Diagnostic>),
+    Aborted(Abort, Box<Diagnostic>),
+}
+
+impl LoxError {
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            LoxError::Io(_) => &[],
+            LoxError::Compile(diagnostics) => diagnostics,
+            LoxError::Runtime(diagnostic) | LoxError::Aborted(_, diagnostic) => {
+                std::slice::from_ref(diagnostic.as_ref())
+            }
+        }
+    }
+}
+
+impl From<Failure> for LoxError {
+    fn from(failure: Failure) -> Self {
+        match failure.abort {
+            Some(abort) => LoxError::Aborted(abort, failure.diagnostic),
+            None => LoxError::Runtime(failure.diagnostic),
+        }
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoxError::Io(error) => write!(f, "failed to read script: {}", error),
+            LoxError::Compile(diagnostics) => {
+                let lines: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            LoxError::Runtime(diagnostic) | LoxError::Aborted(_, diagnostic) => {
+                write!(f, "{}", diagnostic)
+            }
+        }
+    }
+}
+
+impl Error for LoxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoxError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoxError {
+    fn from(error: io::Error) -> Self {
+        LoxError::Io(error)
+    }
+}
+
+// an interpreter session whose globals persist between calls
+pub struct Lox {
+    interpreter: Interpreter,
+    out: Sink,
+    errors: Sink,
+    renderer: Renderer,
+    max_nesting: usize,
+    stack_limit: usize,
+    timeout: Option<Duration>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        let out = output::sink(io::stdout());
+        let errors = output::sink(io::stderr());
+
+        Self {
+            interpreter: Interpreter::new(Rc::clone(&out)),
+            out,
+            errors,
+            renderer: Renderer::new(io::stderr().is_terminal()),
+            max_nesting: parser::DEFAULT_MAX_NESTING,
+            stack_limit: stack::DEFAULT_STACK_LIMIT,
+            timeout: None,
+        }
+    }
+
+    // where `print` output goes, stdout by default
+    pub fn set_stdout<W: Write + 'static>(&mut self, writer: W) {
+        *self.out.borrow_mut() = Box::new(writer);
+    }
+
+    // where error reports go, stderr by default; replacing it turns colour off
+    pub fn set_stderr<W: Write + 'static>(&mut self, writer: W) {
+        *self.errors.borrow_mut() = Box::new(writer);
+        self.renderer.set_color(false);
+    }
+
+    // whether error reports use ANSI colour, on by default only when stderr is a terminal
+    pub fn set_color(&mut self, color: bool) {
+        self.renderer.set_color(color);
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        self.eval_named("<script>", source)
+    }
+
+    // like `eval`, with `name` shown as the file in error reports; diagnostics are
+    // both written to the error sink and returned
+    pub fn eval_named(&mut self, name: &str, source: &str) -> Result<Value, LoxError> {
+        self.renderer.prune();
+        let id = self.renderer.add_source(name, source);
+        let result = self.run(source, id);
+
+        if let Err(error) = &result {
+            let mut errors = self.errors.borrow_mut();
+            for diagnostic in error.diagnostics() {
+                let _ = writeln!(errors, "{}", self.renderer.render(diagnostic));
+            }
+        }
+
+        result
+    }
+
+    // a diagnostic from this session as a source snippet with its span underlined;
+    // code from earlier calls is only kept while functions declared in it are
+    // alive, and is otherwise shown by line and column alone
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        self.renderer.render(diagnostic)
+    }
+
+    fn run(&mut self, source: &str, id: usize) -> Result<Value, LoxError> {
+        let tokens = Scanner::new(source.to_string())
+            .with_source(id)
+            .scan()
+            .map_err(LoxError::Compile)?;
+        let (statements, diagnostics) = Parser::new(tokens)
+            .with_max_nesting(self.max_nesting)
+            .with_stack_limit(self.stack_limit)
+            .parse();
+        if !diagnostics.is_empty() {
+            return Err(LoxError::Compile(diagnostics));
+        }
+        Resolver::new()
+            .resolve(&statements)
+            .map_err(LoxError::Compile)?;
+        self.renderer.retain(id, &statements);
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        self.interpreter.set_deadline(deadline);
+        Ok(self.interpreter.interpret(statements)?)
+    }
+
+    // how deeply expressions and statements may nest while running, script calls
+    // included; going deeper is a "stack overflow" runtime error rather than a
+    // crash of the host
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.interpreter.set_max_depth(max_depth);
+    }
+
+    // how deeply statements and expressions may nest in the source; going deeper
+    // is a syntax error
+    pub fn set_max_nesting(&mut self, max_nesting: usize) {
+        self.max_nesting = max_nesting;
+    }
+
+    // how many bytes of native stack parsing and running a script may each use,
+    // counted from the frame `eval` was called in; going further is a "stack
+    // overflow" error. raise it for scripts run on a thread with a bigger stack
+    // than the default allows for
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack_limit = limit;
+        self.interpreter.set_stack_limit(limit);
+    }
+
+    // how many calls a runtime error's stack trace shows before eliding the rest
+    pub fn set_trace_limit(&mut self, limit: usize) {
+        self.interpreter.set_trace_limit(limit);
+    }
+
+    // limits how many steps scripts may take, one per expression evaluated plus
+    // one per object and reference looked at when collecting cycles, with
+    // `None` lifting the limit; running out stops the script with
+    // `Abort::OutOfFuel`. a stopped script cannot be picked up again, so to let
+    // one carry on past its budget, grant it more from `set_refuel`
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.interpreter.set_fuel(fuel);
+    }
+
+    // tops up the steps left for later calls; does nothing while unlimited
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.interpreter.add_fuel(fuel);
+    }
+
+    // called with the steps taken so far whenever the fuel runs out, before the
+    // script is stopped; returning more fuel resumes it where it was, while `None`
+    // or zero stops it with `Abort::OutOfFuel`
+    pub fn set_refuel<F>(&mut self, refuel: F)
+    where
+        F: FnMut(u64) -> Option<u64> + 'static,
+    {
+        self.interpreter.set_refuel(Some(Box::new(refuel)));
+    }
+
+    // the steps left, or `None` when unlimited
+    pub fn fuel(&self) -> Option<u64> {
+        self.interpreter.fuel()
+    }
+
+    // every step taken by this session, metered or not
+    pub fn fuel_used(&self) -> u64 {
+        self.interpreter.fuel_used()
+    }
+
+    // interrupting the handle, from any thread, stops the script currently running
+    // with `Abort::Interrupted`
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interpreter.interrupt_handle()
+    }
+
+    // how long each call may run before it is stopped with `Abort::Timeout`
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    // caps the bytes held by script strings, instances, functions and scopes;
+    // going over stops the script with `Abort::OutOfMemory`
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.interpreter.heap().set_limit(limit);
+    }
+
+    // bytes held by values this session's scripts have created that are still
+    // alive. values are reference counted, and cycles among them, such as a
+    // closure and the scope it was declared in, are freed by a collection that
+    // runs once enough new objects have been made, so some may stay counted for
+    // a while after they become unreachable
+    pub fn memory_used(&self) -> usize {
+        self.interpreter.heap().current()
+    }
+
+    pub fn peak_memory_used(&self) -> usize {
+        self.interpreter.heap().peak()
+    }
+
+    pub fn set_global<T: IntoLox>(&mut self, name: &str, value: T) {
+        self.interpreter
+            .define_global(name.to_string(), value.into_lox());
+    }
+
+    // exposes a Rust closure to scripts as a global function; an `Err` message is
+    // raised in the script as a runtime error at the call site
+    pub fn register_fn<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&Args) -> Result<Value, String> + 'static,
+    {
+        let native = NativeFunction::new(name, arity, function);
+        self.interpreter
+            .define_global(name.to_string(), Type::Native(Rc::new(native)));
+    }
+
+    // like `register_fn`, but arguments are converted to the closure's parameter
+    // types and its return value back into a script value
+    pub fn register_typed<F, Params>(&mut self, name: &str, function: F)
+    where
+        F: HostFunction<Params> + 'static,
+    {
+        let arity = function.arity();
+        self.register_fn(name, arity, move |args| function.call(args));
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Value, LoxError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        self.eval_named(&path.display().to_string(), &source)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::collect::{self, Teardown, Trace, Unlink};
+use crate::heap::{self, Allocation};
+use crate::parser::Type;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+pub struct Environment {
+    values: HashMap<String, Type>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    allocation: Allocation,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: None,
+            allocation: Allocation::new(mem::size_of::<Environment>()),
+        }
+    }
+
+    pub fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+            allocation: Allocation::new(mem::size_of::<Environment>()),
+        }
+    }
+
+    pub fn define(&mut self, name: String, value: Type) {
+        let size = heap::slot_size(&name);
+        if self.values.insert(name, value).is_none() {
+            self.allocation.grow(size);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Type> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.borrow().get(name),
+        }
+    }
+
+    pub fn get_at(&self, distance: usize, name: &str) -> Option<Type> {
+        match distance {
+            0 => self.values.get(name).cloned(),
+            _ => self.enclosing.as_ref()?.borrow().get_at(distance - 1, name),
+        }
+    }
+
+    pub fn assign_at(&mut self, distance: usize, name: &str, value: Type) -> Option<()> {
+        match distance {
+            0 => {
+                let slot = self.values.get_mut(name)?;
+                *slot = value;
+                Some(())
+            }
+            _ => self
+                .enclosing
+                .as_ref()?
+                .borrow_mut()
+                .assign_at(distance - 1, name, value),
+        }
+    }
+
+    pub fn assign(&mut self, name: &str, value: Type) -> Option<()> {
+        match self.values.get_mut(name) {
+            Some(slot) => {
+                *slot = value;
+                Some(())
+            }
+            None => self.enclosing.as_ref()?.borrow_mut().assign(name, value),
+        }
+    }
+}
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(environment) = self.try_borrow() else {
+            return false;
+        };
+        if let Some(enclosing) = &environment.enclosing {
+            visit(collect::address(enclosing));
+        }
+        environment
+            .values
+            .values()
+            .filter_map(collect::value_address)
+            .for_each(visit);
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut environment) = self.try_borrow_mut() {
+            let values = mem::take(&mut environment.values);
+            drop(environment);
+            drop(values);
+        }
+    }
+}
+
+impl Unlink for Environment {
+    fn unlink(&mut self, teardown: &mut Teardown) {
+        teardown
+            .values
+            .extend(mem::take(&mut self.values).into_values());
+        teardown.scopes.extend(self.enclosing.take());
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        let mut teardown = Teardown::default();
+        self.unlink(&mut teardown);
+        teardown.run();
+    }
+}
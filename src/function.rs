@@ -0,0 +1,77 @@
+use crate::collect::{self, Trace};
+use crate::environment::Environment;
+use crate::heap::{self, Allocation};
+use crate::parser::{FunctionDecl, Type};
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+pub struct Function {
+    pub declaration: Rc<FunctionDecl>,
+    // shared with the defining scope so captured variables stay live and mutable
+    pub closure: Rc<RefCell<Environment>>,
+    pub is_initializer: bool,
+    _allocation: Allocation,
+}
+
+impl Function {
+    pub fn new(
+        declaration: Rc<FunctionDecl>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+            _allocation: Allocation::new(mem::size_of::<Function>()),
+        }
+    }
+
+    // wraps the closure in a scope where `this` refers to the given instance
+    pub fn bind(&self, instance: Type) -> Function {
+        let mut environment = Environment::new_enclosed(Rc::clone(&self.closure));
+        environment.define("this".to_string(), instance);
+
+        Function::new(
+            Rc::clone(&self.declaration),
+            heap::track(RefCell::new(environment)),
+            self.is_initializer,
+        )
+    }
+
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    pub fn name(&self) -> String {
+        self.declaration.name.get_token().get_lexeme()
+    }
+}
+
+impl Trace for Function {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        visit(collect::address(&self.closure));
+        true
+    }
+
+    // functions never change, so cycles through them are broken at their scopes
+    fn clear(&self) {}
+}
+
+// the closure is skipped as it may contain the function itself
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("declaration", &self.declaration)
+            .field("is_initializer", &self.is_initializer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
@@ -0,0 +1,215 @@
+use crate::collect::Trace;
+use crate::parser::Type;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+// how many objects are tracked before the first collection
+const MIN_COLLECT_THRESHOLD: usize = 1024;
+
+// the bytes held by the strings, instances, functions and scopes one interpreter
+// has created, with an optional cap on them
+#[derive(Default)]
+pub struct Heap {
+    current: Cell<usize>,
+    peak: Cell<usize>,
+    limit: Cell<Option<usize>>,
+    // the objects cycles can run through, as made since the last collection or
+    // found live by it
+    tracked: RefCell<Vec<Weak<dyn Trace>>>,
+    // how many tracked objects there may be before the next collection
+    threshold: Cell<usize>,
+}
+
+thread_local! {
+    // the heap new values are charged to, set while an interpreter is running
+    static ACTIVE: RefCell<Option<Rc<Heap>>> = const { RefCell::new(None) };
+}
+
+impl Heap {
+    pub fn current(&self) -> usize {
+        self.current.get()
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.get()
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit.get()
+    }
+
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit.set(limit);
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.would_exceed(0)
+    }
+
+    // whether allocating `bytes` more would take usage past the limit
+    pub fn would_exceed(&self, bytes: usize) -> bool {
+        match self.limit.get() {
+            Some(limit) => self.current.get().saturating_add(bytes) > limit,
+            None => false,
+        }
+    }
+
+    // due once the objects made since the last collection outnumber the ones it
+    // kept, so collecting costs a bounded amount per object made
+    pub fn should_collect(&self) -> bool {
+        self.tracked.borrow().len() >= self.threshold.get().max(MIN_COLLECT_THRESHOLD)
+    }
+
+    pub fn take_tracked(&self) -> Vec<Weak<dyn Trace>> {
+        mem::take(&mut self.tracked.borrow_mut())
+    }
+
+    // restarts tracking from the objects a collection kept
+    pub fn retrack(&self, survivors: Vec<Weak<dyn Trace>>) {
+        self.threshold.set(survivors.len() * 2);
+        *self.tracked.borrow_mut() = survivors;
+    }
+
+    // charges values created on this thread to this heap until the guard is dropped
+    pub fn activate(self: &Rc<Self>) -> ActiveHeap {
+        let previous = ACTIVE.with(|active| active.replace(Some(Rc::clone(self))));
+        ActiveHeap { previous }
+    }
+
+    fn active() -> Option<Rc<Heap>> {
+        ACTIVE.with(|active| active.borrow().clone())
+    }
+
+    fn charge(&self, bytes: usize) {
+        let current = self.current.get() + bytes;
+        self.current.set(current);
+        self.peak.set(self.peak.get().max(current));
+    }
+
+    fn release(&self, bytes: usize) {
+        self.current.set(self.current.get() - bytes);
+    }
+}
+
+pub struct ActiveHeap {
+    previous: Option<Rc<Heap>>,
+}
+
+impl Drop for ActiveHeap {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE.with(|active| *active.borrow_mut() = previous);
+    }
+}
+
+// bytes charged to the heap that was active when it was made, until dropped;
+// values made while no interpreter is running are not charged anywhere
+pub struct Allocation {
+    heap: Option<Rc<Heap>>,
+    bytes: usize,
+}
+
+impl Allocation {
+    pub fn new(bytes: usize) -> Self {
+        let heap = Heap::active();
+        if let Some(heap) = &heap {
+            heap.charge(bytes);
+        }
+        Self { heap, bytes }
+    }
+
+    pub fn grow(&mut self, bytes: usize) {
+        if let Some(heap) = &self.heap {
+            heap.charge(bytes);
+        }
+        self.bytes += bytes;
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        if let Some(heap) = &self.heap {
+            heap.release(self.bytes);
+        }
+    }
+}
+
+// puts a scope, function, class or instance where the collector can find it
+pub fn track<T: Trace + 'static>(object: T) -> Rc<T> {
+    let object = Rc::new(object);
+    if let Some(heap) = Heap::active() {
+        let weak: Weak<T> = Rc::downgrade(&object);
+        heap.tracked.borrow_mut().push(weak);
+    }
+    object
+}
+
+// the cost of a named slot holding a value, as in a scope or an instance
+pub fn slot_size(name: &str) -> usize {
+    name.len() + mem::size_of::<Type>()
+}
+
+// an immutable script string; copies share the text, so only making a new
+// string is charged to the heap
+#[derive(Clone)]
+pub struct LoxString(Rc<StringData>);
+
+struct StringData {
+    text: String,
+    _allocation: Allocation,
+}
+
+impl LoxString {
+    pub fn new(text: String) -> Self {
+        let allocation = Allocation::new(text.len());
+        LoxString(Rc::new(StringData {
+            text,
+            _allocation: allocation,
+        }))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0.text
+    }
+}
+
+impl Deref for LoxString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for LoxString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl From<String> for LoxString {
+    fn from(text: String) -> Self {
+        LoxString::new(text)
+    }
+}
+
+impl From<&str> for LoxString {
+    fn from(text: &str) -> Self {
+        LoxString::new(text.to_string())
+    }
+}
+
+impl fmt::Debug for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
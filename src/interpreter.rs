@@ -1,96 +1,663 @@
-use crate::parser::{Expr, Type};
+use crate::class::{Class, Instance};
+use crate::collect;
+use crate::diagnostic::{Diagnostic, Phase, Span, StackFrame};
+use crate::environment::Environment;
+use crate::function::Function;
+use crate::heap::{self, Heap};
+use crate::interrupt::InterruptHandle;
+use crate::native::{self, NativeFunction};
+use crate::output::Sink;
+use crate::parser::{Expr, FunctionDecl, Stmt, Type};
+use crate::stack::{self, StackGuard};
 use crate::tokenizer::{CtxToken, Token};
+use crate::userdata;
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::Instant;
+
+// how deeply expressions, statements and calls may nest at run time; the native
+// stack they use is bounded separately, see `stack`
+const DEFAULT_MAX_DEPTH: usize = 1024;
+// how many steps pass between looks at the clock when a deadline is set
+const DEADLINE_INTERVAL: u64 = 1024;
 
 enum RuntimeError {
     TypeError(CtxToken),
     ZeroDivisionError(CtxToken),
+    UndefinedVariable(CtxToken),
+    NotCallable(CtxToken),
+    ArityError(CtxToken, usize, usize),
+    NotAnInstance(CtxToken),
+    UndefinedProperty(CtxToken),
+    SuperclassNotClass(CtxToken),
+    NativeError(CtxToken, String),
+    StackOverflow(Span, usize),
+    StackExhausted(Span, usize),
+    OutOfFuel(Span),
+    Interrupted(Span),
+    Timeout(Span),
+    OutOfMemory(Span, usize),
+    // not an error: unwinds out of a function body carrying its return value
+    Return(Type),
+}
+
+// a limit set by the embedder that stopped a script part way through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Abort {
+    OutOfFuel,
+    Interrupted,
+    Timeout,
+    OutOfMemory,
 }
 
-pub struct Interpreter {}
+// a runtime error as reported, and the limit that stopped the script if it
+// was one of the embedder's
+pub struct Failure {
+    pub diagnostic: Box<Diagnostic>,
+    pub abort: Option<Abort>,
+}
+
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    out: Sink,
+    // functions currently being called, outermost first
+    frames: Vec<StackFrame>,
+    // the frames active where the error being unwound was raised
+    trace: Vec<StackFrame>,
+    // how many frames an error reports before the rest are elided
+    trace_limit: usize,
+    // how many expressions and statements are being run inside one another, calls
+    // included
+    depth: usize,
+    max_depth: usize,
+    // how much native stack the current call has used
+    stack: StackGuard,
+    // steps left before the program is stopped, unlimited when `None`
+    fuel: Option<u64>,
+    fuel_used: u64,
+    // asked for more fuel when it runs out, given the steps taken so far
+    refuel: Option<Box<dyn FnMut(u64) -> Option<u64>>>,
+    interrupt: InterruptHandle,
+    deadline: Option<Instant>,
+    heap: Rc<Heap>,
+}
 
 impl Interpreter {
-    fn error(token: CtxToken, message: &str) {
-        eprintln!("ERROR RUNTIME {}: {}", token, message);
+    pub fn new(out: Sink) -> Self {
+        let heap = Rc::new(Heap::default());
+        let _active = heap.activate();
+
+        let mut environment = Environment::new();
+        native::define_globals(&mut environment, &out);
+        let globals = heap::track(RefCell::new(environment));
+
+        Self {
+            globals: Rc::clone(&globals),
+            environment: globals,
+            out,
+            frames: Vec::new(),
+            trace: Vec::new(),
+            trace_limit: 16,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            stack: StackGuard::new(stack::DEFAULT_STACK_LIMIT),
+            fuel: None,
+            fuel_used: 0,
+            refuel: None,
+            interrupt: InterruptHandle::new(),
+            deadline: None,
+            heap: Rc::clone(&heap),
+        }
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack = StackGuard::new(limit);
+    }
+
+    pub fn set_trace_limit(&mut self, limit: usize) {
+        self.trace_limit = limit;
+    }
+
+    fn error(token: CtxToken, message: &str) -> Diagnostic {
+        Diagnostic::error(Phase::Runtime, message, token.span())
+    }
+
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(remaining) = &mut self.fuel {
+            *remaining = remaining.saturating_add(fuel);
+        }
+    }
+
+    pub fn set_refuel(&mut self, refuel: Option<Box<dyn FnMut(u64) -> Option<u64>>>) {
+        self.refuel = refuel;
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn fuel_used(&self) -> u64 {
+        self.fuel_used
+    }
+
+    pub fn define_global(&mut self, name: String, value: Type) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    // returns the value of the program's final statement if it is an expression
+    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<Type, Failure> {
+        let _active = self.heap.activate();
+        self.stack = StackGuard::new(self.stack.limit());
+        let mut value = Type::Nil;
+
+        for statement in statements.iter() {
+            let result = match statement {
+                Stmt::Expression { expr } => self.evaluate(expr),
+                _ => self.execute(statement).map(|()| Type::Nil),
+            };
+
+            match result {
+                Ok(result) => value = result,
+                Err(error) => {
+                    let abort = match *error {
+                        RuntimeError::OutOfFuel(..) => Some(Abort::OutOfFuel),
+                        RuntimeError::Interrupted(..) => Some(Abort::Interrupted),
+                        RuntimeError::Timeout(..) => Some(Abort::Timeout),
+                        RuntimeError::OutOfMemory(..) => Some(Abort::OutOfMemory),
+                        _ => None,
+                    };
+                    let diagnostic = Box::new(self.attach_trace(Interpreter::diagnostic(*error)));
+                    return Err(Failure { diagnostic, abort });
+                }
+            }
+        }
+
+        Ok(value)
+    }
+
+    // innermost frames first, eliding the outer ones of a deep recursion
+    fn attach_trace(&mut self, diagnostic: Diagnostic) -> Diagnostic {
+        let mut trace = std::mem::take(&mut self.trace);
+        trace.reverse();
+
+        let elided = trace.len().saturating_sub(self.trace_limit);
+        trace.truncate(self.trace_limit);
+
+        let diagnostic = diagnostic.with_trace(trace);
+        match elided {
+            0 => diagnostic,
+            _ => diagnostic.with_note(format!("{} more calls not shown", elided)),
+        }
     }
 
-    pub fn interpret(expr: Expr) -> Result<(), ()> {
-        match Interpreter::evaluate(Box::new(expr)) {
-            Ok(literal) => Ok(println!("{}", literal)),
-            Err(RuntimeError::TypeError(token)) => {
-                Err(Interpreter::error(token, "invalid type(s) for operator"))
+    fn diagnostic(error: RuntimeError) -> Diagnostic {
+        match error {
+            RuntimeError::TypeError(token) => {
+                let message = format!(
+                    "invalid type(s) for operator '{}'",
+                    token.get_token().get_lexeme()
+                );
+                Interpreter::error(token, &message)
             }
-            Err(RuntimeError::ZeroDivisionError(token)) => {
-                Err(Interpreter::error(token, "zero division error"))
+            RuntimeError::ZeroDivisionError(token) => {
+                Interpreter::error(token, "zero division error")
             }
+            RuntimeError::UndefinedVariable(token) => {
+                let message = format!("undefined variable '{}'", token.get_token().get_lexeme());
+                Interpreter::error(token, &message)
+            }
+            RuntimeError::NotCallable(token) => {
+                Interpreter::error(token, "can only call functions and classes")
+            }
+            RuntimeError::ArityError(token, expected, got) => Interpreter::error(
+                token,
+                &format!("expected {} arguments but got {}", expected, got),
+            ),
+            RuntimeError::NotAnInstance(token) => {
+                Interpreter::error(token, "only instances have properties")
+            }
+            RuntimeError::UndefinedProperty(token) => {
+                let message = format!("undefined property '{}'", token.get_token().get_lexeme());
+                Interpreter::error(token, &message)
+            }
+            RuntimeError::SuperclassNotClass(token) => {
+                Interpreter::error(token, "superclass must be a class")
+            }
+            RuntimeError::NativeError(token, message) => Interpreter::error(token, &message),
+            RuntimeError::StackOverflow(span, max_depth) => {
+                Diagnostic::error(Phase::Runtime, "stack overflow", span).with_note(format!(
+                    "evaluation is limited to {} levels of nesting",
+                    max_depth
+                ))
+            }
+            RuntimeError::StackExhausted(span, limit) => {
+                Diagnostic::error(Phase::Runtime, "stack overflow", span).with_note(format!(
+                    "evaluation is limited to {} KiB of native stack",
+                    limit / 1024
+                ))
+            }
+            RuntimeError::OutOfFuel(span) => Diagnostic::error(Phase::Runtime, "out of fuel", span)
+                .with_note("every expression evaluated uses one step of fuel"),
+            RuntimeError::Interrupted(span) => {
+                Diagnostic::error(Phase::Runtime, "interrupted", span)
+            }
+            RuntimeError::Timeout(span) => Diagnostic::error(Phase::Runtime, "timed out", span),
+            RuntimeError::OutOfMemory(span, limit) => {
+                Diagnostic::error(Phase::Runtime, "out of memory", span)
+                    .with_note(format!("scripts are limited to {} bytes", limit))
+            }
+            // rejected by the resolver before the program runs
+            RuntimeError::Return(_) => unreachable!(),
         }
     }
 
-    fn evaluate(expr: Box<Expr>) -> Result<Type, RuntimeError> {
-        match *expr {
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => Interpreter::evaluate_binary(left, operator, right),
-            Expr::Grouping { expr } => Interpreter::evaluate_grouping(expr),
-            Expr::Literal { value } => Ok(value),
-            Expr::Unary { operator, expr } => Interpreter::evaluate_unary(operator, expr),
+    // statements count towards the depth limit too, as a function body nested in
+    // blocks recurses through here once per level; the limit itself is checked by
+    // the next expression, which the parser's nesting limit keeps close by
+    fn execute(&mut self, statement: &Stmt) -> Result<(), Box<RuntimeError>> {
+        self.depth += 1;
+        let result = self.execute_stmt(statement);
+        self.depth -= 1;
+        result
+    }
+
+    // each kind of statement is run by its own method, keeping this frame, which
+    // recursion passes through, small
+    fn execute_stmt(&mut self, statement: &Stmt) -> Result<(), Box<RuntimeError>> {
+        match statement {
+            Stmt::Expression { expr } => self.evaluate(expr).map(drop),
+            Stmt::Print { expr } => self.execute_print(expr),
+            Stmt::Var { name, initializer } => self.execute_var(name, initializer.as_ref()),
+            Stmt::Block { statements } => {
+                let environment = Environment::new_enclosed(Rc::clone(&self.environment));
+                self.execute_block(statements, environment)
+            }
+            Stmt::If {
+                condition,
+                then,
+                otherwise,
+            } => self.execute_if(condition, then, otherwise.as_deref()),
+            Stmt::While { condition, body } => self.execute_while(condition, body),
+            Stmt::Function { declaration } => {
+                self.execute_function(declaration);
+                Ok(())
+            }
+            Stmt::Return { value, .. } => self.execute_return(value.as_ref()),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => self.execute_class(name, superclass.as_ref(), methods),
+        }
+    }
+
+    fn execute_print(&mut self, expr: &Expr) -> Result<(), Box<RuntimeError>> {
+        let text = Interpreter::stringify(self.evaluate(expr)?);
+        let _ = writeln!(self.out.borrow_mut(), "{}", text);
+        Ok(())
+    }
+
+    fn execute_var(
+        &mut self,
+        name: &CtxToken,
+        initializer: Option<&Expr>,
+    ) -> Result<(), Box<RuntimeError>> {
+        let value = match initializer {
+            Some(initializer) => self.evaluate(initializer)?,
+            None => Type::Nil,
+        };
+        self.environment
+            .borrow_mut()
+            .define(name.get_token().get_lexeme(), value);
+        Ok(())
+    }
+
+    fn execute_if(
+        &mut self,
+        condition: &Expr,
+        then: &Stmt,
+        otherwise: Option<&Stmt>,
+    ) -> Result<(), Box<RuntimeError>> {
+        if Interpreter::is_truthy(self.evaluate(condition)?) {
+            self.execute(then)
+        } else if let Some(otherwise) = otherwise {
+            self.execute(otherwise)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn execute_while(&mut self, condition: &Expr, body: &Stmt) -> Result<(), Box<RuntimeError>> {
+        while Interpreter::is_truthy(self.evaluate(condition)?) {
+            self.execute(body)?;
+        }
+        Ok(())
+    }
+
+    fn execute_function(&mut self, declaration: &Rc<FunctionDecl>) {
+        let function = Function::new(Rc::clone(declaration), Rc::clone(&self.environment), false);
+        self.environment
+            .borrow_mut()
+            .define(function.name(), Type::Function(heap::track(function)));
+    }
+
+    fn execute_return(&mut self, value: Option<&Expr>) -> Result<(), Box<RuntimeError>> {
+        let value = match value {
+            Some(value) => self.evaluate(value)?,
+            None => Type::Nil,
+        };
+        Err(RuntimeError::Return(value).into())
+    }
+
+    fn execute_class(
+        &mut self,
+        name: &CtxToken,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDecl>],
+    ) -> Result<(), Box<RuntimeError>> {
+        let superclass = match superclass {
+            Some(expr) => match self.evaluate(expr)? {
+                Type::Class(class) => Some(class),
+                _ => match expr {
+                    Expr::Variable { name, .. } => {
+                        return Err(RuntimeError::SuperclassNotClass(name.clone()).into())
+                    }
+                    _ => unreachable!(),
+                },
+            },
+            None => None,
+        };
+
+        let enclosing = Rc::clone(&self.environment);
+        if let Some(superclass) = &superclass {
+            let mut environment = Environment::new_enclosed(Rc::clone(&enclosing));
+            environment.define("super".to_string(), Type::Class(Rc::clone(superclass)));
+            self.environment = heap::track(RefCell::new(environment));
+        }
+
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let method_name = method.name.get_token().get_lexeme();
+                let function = Function::new(
+                    Rc::clone(method),
+                    Rc::clone(&self.environment),
+                    method_name == "init",
+                );
+                (method_name, heap::track(function))
+            })
+            .collect();
+
+        let class = Class::new(name.get_token().get_lexeme(), superclass, methods);
+        self.environment = enclosing;
+        self.environment
+            .borrow_mut()
+            .define(class.name.clone(), Type::Class(heap::track(class)));
+
+        Ok(())
+    }
+
+    fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Environment,
+    ) -> Result<(), Box<RuntimeError>> {
+        let previous = std::mem::replace(
+            &mut self.environment,
+            heap::track(RefCell::new(environment)),
+        );
+
+        // restore the enclosing scope even when a statement fails
+        let mut result = Ok(());
+        for statement in statements {
+            result = self.execute(statement);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.environment = previous;
+
+        result
+    }
+
+    // tops up an empty tank from the embedder's hook, if it has one and grants any
+    fn refuel(&mut self) -> bool {
+        let granted = match &mut self.refuel {
+            Some(refuel) => refuel(self.fuel_used),
+            None => None,
+        };
+        match granted {
+            Some(fuel) if fuel > 0 => {
+                self.fuel = Some(fuel);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // every expression and every script call passes through here, so this is
+    // where runaway recursion is stopped, fuel is metered and a script can be
+    // cancelled
+    fn evaluate(&mut self, expr: &Expr) -> Result<Type, Box<RuntimeError>> {
+        self.step(expr.span())?;
+
+        self.depth += 1;
+        let result = self.evaluate_expr(expr);
+        self.depth -= 1;
+        result
+    }
+
+    // uses one step of fuel and checks the other limits, away from the frames
+    // recursion passes through
+    fn step(&mut self, span: Span) -> Result<(), Box<RuntimeError>> {
+        if self.depth >= self.max_depth {
+            return Err(RuntimeError::StackOverflow(span, self.max_depth).into());
+        }
+        if self.stack.is_exhausted() {
+            return Err(RuntimeError::StackExhausted(span, self.stack.limit()).into());
+        }
+        if self.fuel == Some(0) && !self.refuel() {
+            return Err(RuntimeError::OutOfFuel(span).into());
+        }
+        if let Some(fuel) = &mut self.fuel {
+            *fuel -= 1;
+        }
+        self.fuel_used += 1;
+
+        if self.interrupt.take() {
+            return Err(RuntimeError::Interrupted(span).into());
+        }
+        if let Some(deadline) = self.deadline {
+            if self.fuel_used.is_multiple_of(DEADLINE_INTERVAL) && Instant::now() >= deadline {
+                return Err(RuntimeError::Timeout(span).into());
+            }
+        }
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        if self.heap.is_exceeded() {
+            // cycles are only freed by a collection, so try one before giving up
+            self.collect_garbage();
+            if self.heap.is_exceeded() {
+                return Err(self.out_of_memory(span));
+            }
+        }
+        Ok(())
+    }
+
+    // frees unreachable cycles, charging the objects and references looked at to
+    // fuel. an empty tank is left for the next expression to report
+    fn collect_garbage(&mut self) {
+        let work = collect::collect(&self.heap) as u64;
+        self.fuel_used += work;
+        if let Some(fuel) = &mut self.fuel {
+            *fuel = fuel.saturating_sub(work);
+        }
+    }
+
+    fn out_of_memory(&self, span: Span) -> Box<RuntimeError> {
+        Box::new(RuntimeError::OutOfMemory(
+            span,
+            self.heap.limit().unwrap_or_default(),
+        ))
+    }
+
+    fn evaluate_expr(&mut self, expr: &Expr) -> Result<Type, Box<RuntimeError>> {
+        match expr {
+            Expr::Binary { .. } | Expr::Logical { .. } | Expr::Call { .. } | Expr::Get { .. } => {
+                self.evaluate_chain(expr)
+            }
+            Expr::Grouping { expr, .. } => self.evaluate_grouping(expr),
+            Expr::Literal { value, .. } => Ok(value.clone()),
+            Expr::Unary { operator, expr, .. } => self.evaluate_unary(operator, expr),
             Expr::Ternary {
                 condition,
                 then,
                 otherwise,
-            } => Interpreter::evaluate_ternary(condition, then, otherwise),
+                ..
+            } => self.evaluate_ternary(condition, then, otherwise),
+            Expr::Variable { name, depth, .. } => self.evaluate_variable(name, depth.get()),
+            Expr::Assign {
+                name, value, depth, ..
+            } => self.evaluate_assign(name, value, depth.get()),
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => self.evaluate_set(object, name, value),
+            Expr::This { keyword, depth, .. } => self.evaluate_variable(keyword, depth.get()),
+            Expr::Super { method, depth, .. } => self.evaluate_super(method, depth.get()),
+        }
+    }
+
+    // a chain of operators, calls and property accesses such as `a + b + c`
+    // nests to the left, so it is run from where it starts along in a loop
+    fn evaluate_chain(&mut self, expr: &Expr) -> Result<Type, Box<RuntimeError>> {
+        let mut links = Vec::new();
+        let mut first = expr;
+        while let Some(inner) = first.chained() {
+            // the outermost link has been charged by `evaluate` already
+            if !links.is_empty() {
+                self.step(first.span())?;
+            }
+            links.push(first);
+            first = inner;
+        }
+
+        let mut value = self.evaluate(first)?;
+        for link in links.into_iter().rev() {
+            value = self.evaluate_link(link, value)?;
+        }
+        Ok(value)
+    }
+
+    // applies one link of a chain to the value of everything before it
+    fn evaluate_link(&mut self, link: &Expr, left: Type) -> Result<Type, Box<RuntimeError>> {
+        match link {
+            Expr::Binary {
+                operator, right, ..
+            } => {
+                let right = self.evaluate(right)?;
+                self.evaluate_binary(operator, left, right)
+            }
+            // short-circuit, yielding whichever operand decided the result
+            Expr::Logical {
+                operator, right, ..
+            } => match (operator.get_token(), Interpreter::is_truthy(left.clone())) {
+                (Token::Or, true) | (Token::And, false) => Ok(left),
+                _ => self.evaluate(right),
+            },
+            Expr::Call {
+                paren,
+                arguments,
+                span,
+                ..
+            } => {
+                let values = self.evaluate_arguments(arguments)?;
+                self.call(left, values, paren, *span)
+            }
+            Expr::Get { name, .. } => Interpreter::get_property(left, name),
+            _ => unreachable!(),
         }
     }
 
-    // wrong lint: https://github.com/rust-lang/rust/issues/41620#issuecomment-1722194944
-    #[allow(illegal_floating_point_literal_pattern)]
     fn evaluate_binary(
-        left: Box<Expr>,
-        operator: CtxToken,
-        right: Box<Expr>,
-    ) -> Result<Type, RuntimeError> {
-        let left = Interpreter::evaluate(left)?;
-        let right = Interpreter::evaluate(right)?;
+        &mut self,
+        operator: &CtxToken,
+        left: Type,
+        right: Type,
+    ) -> Result<Type, Box<RuntimeError>> {
+        let operator = operator.clone();
 
         match operator.get_token() {
             Token::Plus => match (left, right) {
                 (Type::Number(left), Type::Number(right)) => Ok(Type::Number(left + right)),
                 (Type::String(left), Type::String(right)) => {
-                    Ok(Type::String(format!("{}{}", left, right)))
+                    // refused up front, as a doubling string outgrows any limit quickly
+                    let bytes = left.len() + right.len();
+                    if self.heap.would_exceed(bytes) {
+                        self.collect_garbage();
+                        if self.heap.would_exceed(bytes) {
+                            return Err(self.out_of_memory(operator.span()));
+                        }
+                    }
+                    Ok(Type::String(format!("{}{}", left, right).into()))
                 }
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::Minus => match (left, right) {
                 (Type::Number(left), Type::Number(right)) => Ok(Type::Number(left - right)),
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::Star => match (left, right) {
                 (Type::Number(left), Type::Number(right)) => Ok(Type::Number(left * right)),
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::Slash => match (left, right) {
                 (Type::Number(left), Type::Number(right)) => match right {
-                    0. => Err(RuntimeError::ZeroDivisionError(operator)),
+                    0. => Err(RuntimeError::ZeroDivisionError(operator).into()),
                     _ => Ok(Type::Number(left / right)),
                 },
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::Greater => match (left, right) {
                 (Type::Number(left), Type::Number(right)) => Ok(Type::Bool(left > right)),
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::GreaterEqual => match (left, right) {
                 (Type::Number(left), Type::Number(right)) => Ok(Type::Bool(left >= right)),
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::Less => match (left, right) {
                 (Type::Number(left), Type::Number(right)) => Ok(Type::Bool(left < right)),
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::LessEqual => match (left, right) {
                 (Type::Number(left), Type::Number(right)) => Ok(Type::Bool(left <= right)),
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::EqualEqual => Ok(Type::Bool(Interpreter::is_equal(left, right))),
             Token::BangEqual => Ok(Type::Bool(!Interpreter::is_equal(left, right))),
@@ -98,17 +665,149 @@ impl Interpreter {
         }
     }
 
-    fn evaluate_grouping(expr: Box<Expr>) -> Result<Type, RuntimeError> {
-        Interpreter::evaluate(expr)
+    fn evaluate_arguments(&mut self, arguments: &[Expr]) -> Result<Vec<Type>, Box<RuntimeError>> {
+        let mut values: Vec<Type> = Vec::new();
+        for argument in arguments {
+            values.push(self.evaluate(argument)?);
+        }
+        Ok(values)
+    }
+
+    fn call(
+        &mut self,
+        callee: Type,
+        values: Vec<Type>,
+        paren: &CtxToken,
+        call_site: Span,
+    ) -> Result<Type, Box<RuntimeError>> {
+        Interpreter::check_arity(&callee, values.len(), paren)?;
+
+        match callee {
+            Type::Function(function) => self.call_function(&function, values, call_site),
+            Type::Native(native) => Interpreter::call_native(&native, &values, paren),
+            Type::Class(class) => self.instantiate(class, values, call_site),
+            _ => unreachable!(),
+        }
     }
 
-    fn evaluate_unary(operator: CtxToken, expr: Box<Expr>) -> Result<Type, RuntimeError> {
-        let literal = Interpreter::evaluate(expr)?;
+    fn check_arity(callee: &Type, count: usize, paren: &CtxToken) -> Result<(), Box<RuntimeError>> {
+        let arity = match callee {
+            Type::Function(function) => function.arity(),
+            Type::Native(native) => native.arity,
+            Type::Class(class) => class.arity(),
+            _ => return Err(RuntimeError::NotCallable(paren.clone()).into()),
+        };
+        match count == arity {
+            true => Ok(()),
+            false => Err(RuntimeError::ArityError(paren.clone(), arity, count).into()),
+        }
+    }
+
+    fn call_native(
+        native: &NativeFunction,
+        arguments: &[Type],
+        paren: &CtxToken,
+    ) -> Result<Type, Box<RuntimeError>> {
+        native.call(arguments).map_err(|message| {
+            Box::new(RuntimeError::NativeError(
+                paren.clone(),
+                format!("{}: {}", native.name, message),
+            ))
+        })
+    }
+
+    fn instantiate(
+        &mut self,
+        class: Rc<Class>,
+        arguments: Vec<Type>,
+        call_site: Span,
+    ) -> Result<Type, Box<RuntimeError>> {
+        let instance = Type::Instance(heap::track(RefCell::new(Instance::new(class.clone()))));
+        if let Some(initializer) = class.find_method("init") {
+            let initializer = initializer.bind(instance.clone());
+            self.call_function(&initializer, arguments, call_site)?;
+        }
+        Ok(instance)
+    }
+
+    fn call_function(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Type>,
+        call_site: Span,
+    ) -> Result<Type, Box<RuntimeError>> {
+        let environment = self.enter_call(function, arguments, call_site);
+        let result = self.execute_block(&function.declaration.body, environment);
+        self.leave_call(function, result)
+    }
+
+    // binds the arguments in a new scope and records the call for stack traces
+    fn enter_call(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Type>,
+        call_site: Span,
+    ) -> Environment {
+        let mut environment = Environment::new_enclosed(Rc::clone(&function.closure));
+        for (param, argument) in function.declaration.params.iter().zip(arguments) {
+            environment.define(param.get_token().get_lexeme(), argument);
+        }
+
+        self.frames.push(StackFrame {
+            function: function.name(),
+            call_site,
+        });
+        environment
+    }
+
+    fn leave_call(
+        &mut self,
+        function: &Function,
+        result: Result<(), Box<RuntimeError>>,
+    ) -> Result<Type, Box<RuntimeError>> {
+        // the innermost call an error unwinds through sees the whole stack
+        let unwinding =
+            matches!(result, Err(ref error) if !matches!(**error, RuntimeError::Return(_)));
+        if unwinding && self.trace.is_empty() {
+            self.trace = self.frames.clone();
+        }
+        self.frames.pop();
+
+        let value = match result {
+            Ok(()) => Type::Nil,
+            Err(error) => match *error {
+                RuntimeError::Return(value) => value,
+                error => return Err(Box::new(error)),
+            },
+        };
+
+        // initializers always hand back the instance, even on an early return
+        match function.is_initializer {
+            true => Ok(function
+                .closure
+                .borrow()
+                .get_at(0, "this")
+                .expect("initializer bound to an instance")),
+            false => Ok(value),
+        }
+    }
+
+    fn evaluate_grouping(&mut self, expr: &Expr) -> Result<Type, Box<RuntimeError>> {
+        self.evaluate(expr)
+    }
+
+    fn evaluate_unary(
+        &mut self,
+        operator: &CtxToken,
+        expr: &Expr,
+    ) -> Result<Type, Box<RuntimeError>> {
+        let literal = self.evaluate(expr)?;
+        let operator = operator.clone();
 
         match operator.get_token() {
             Token::Minus => match literal {
                 Type::Number(value) => Ok(Type::Number(-value)),
-                _ => Err(RuntimeError::TypeError(operator)),
+                _ => Err(RuntimeError::TypeError(operator).into()),
             },
             Token::Bang => Ok(Type::Bool(!Interpreter::is_truthy(literal))),
             _ => panic!(),
@@ -116,13 +815,110 @@ impl Interpreter {
     }
 
     fn evaluate_ternary(
-        condition: Box<Expr>,
-        then: Box<Expr>,
-        otherwise: Box<Expr>,
-    ) -> Result<Type, RuntimeError> {
-        match Interpreter::is_truthy(Interpreter::evaluate(condition)?) {
-            true => Interpreter::evaluate(then),
-            false => Interpreter::evaluate(otherwise),
+        &mut self,
+        condition: &Expr,
+        then: &Expr,
+        otherwise: &Expr,
+    ) -> Result<Type, Box<RuntimeError>> {
+        match Interpreter::is_truthy(self.evaluate(condition)?) {
+            true => self.evaluate(then),
+            false => self.evaluate(otherwise),
+        }
+    }
+
+    fn evaluate_variable(
+        &mut self,
+        name: &CtxToken,
+        depth: Option<usize>,
+    ) -> Result<Type, Box<RuntimeError>> {
+        let lexeme = name.get_token().get_lexeme();
+
+        match depth {
+            Some(distance) => self.environment.borrow().get_at(distance, &lexeme),
+            None => self.globals.borrow().get(&lexeme),
+        }
+        .ok_or_else(|| Box::new(RuntimeError::UndefinedVariable(name.clone())))
+    }
+
+    fn evaluate_assign(
+        &mut self,
+        name: &CtxToken,
+        value: &Expr,
+        depth: Option<usize>,
+    ) -> Result<Type, Box<RuntimeError>> {
+        let value = self.evaluate(value)?;
+        let lexeme = name.get_token().get_lexeme();
+
+        match depth {
+            Some(distance) => {
+                self.environment
+                    .borrow_mut()
+                    .assign_at(distance, &lexeme, value.clone())
+            }
+            None => self.globals.borrow_mut().assign(&lexeme, value.clone()),
+        }
+        .ok_or_else(|| Box::new(RuntimeError::UndefinedVariable(name.clone())))?;
+
+        Ok(value)
+    }
+
+    fn get_property(object: Type, name: &CtxToken) -> Result<Type, Box<RuntimeError>> {
+        match object {
+            Type::Instance(instance) => Instance::get(&instance, &name.get_token().get_lexeme())
+                .ok_or_else(|| Box::new(RuntimeError::UndefinedProperty(name.clone()))),
+            Type::Userdata(data) => userdata::get(&data, &name.get_token().get_lexeme())
+                .ok_or_else(|| Box::new(RuntimeError::UndefinedProperty(name.clone()))),
+            _ => Err(RuntimeError::NotAnInstance(name.clone()).into()),
+        }
+    }
+
+    fn evaluate_set(
+        &mut self,
+        object: &Expr,
+        name: &CtxToken,
+        value: &Expr,
+    ) -> Result<Type, Box<RuntimeError>> {
+        let object = self.evaluate(object)?;
+        if !matches!(object, Type::Instance(_) | Type::Userdata(_)) {
+            return Err(RuntimeError::NotAnInstance(name.clone()).into());
+        }
+
+        let value = self.evaluate(value)?;
+        match object {
+            Type::Instance(instance) => instance
+                .borrow_mut()
+                .set(name.get_token().get_lexeme(), value.clone()),
+            Type::Userdata(data) => data
+                .borrow_mut()
+                .set(&name.get_token().get_lexeme(), value.clone())
+                .map_err(|message| Box::new(RuntimeError::NativeError(name.clone(), message)))?,
+            _ => unreachable!(),
+        }
+
+        Ok(value)
+    }
+
+    fn evaluate_super(
+        &mut self,
+        method: &CtxToken,
+        depth: Option<usize>,
+    ) -> Result<Type, Box<RuntimeError>> {
+        let distance = depth.expect("super resolved inside a subclass");
+        let environment = self.environment.borrow();
+
+        // `this` lives in the scope just inside the one binding `super`
+        let superclass = match environment.get_at(distance, "super") {
+            Some(Type::Class(class)) => class,
+            _ => unreachable!(),
+        };
+        let object = environment
+            .get_at(distance - 1, "this")
+            .expect("super used inside a method");
+
+        let method_name = method.get_token().get_lexeme();
+        match superclass.find_method(&method_name) {
+            Some(function) => Ok(Type::Function(heap::track(function.bind(object)))),
+            None => Err(RuntimeError::UndefinedProperty(method.clone()).into()),
         }
     }
 
@@ -130,8 +926,20 @@ impl Interpreter {
         match value {
             Type::Nil => false,
             Type::Bool(value) => value,
-            Type::String(value) => value != "",
+            Type::String(value) => !value.is_empty(),
             Type::Number(value) => value != 0.,
+            Type::Function(_)
+            | Type::Native(_)
+            | Type::Class(_)
+            | Type::Instance(_)
+            | Type::Userdata(_) => true,
+        }
+    }
+
+    pub fn stringify(value: Type) -> String {
+        match value {
+            Type::String(value) => value.to_string(),
+            _ => format!("{}", value),
         }
     }
 
@@ -141,6 +949,11 @@ impl Interpreter {
             (Type::Bool(left), Type::Bool(right)) => left == right,
             (Type::String(left), Type::String(right)) => left == right,
             (Type::Number(left), Type::Number(right)) => left == right,
+            (Type::Function(left), Type::Function(right)) => Rc::ptr_eq(&left, &right),
+            (Type::Native(left), Type::Native(right)) => Rc::ptr_eq(&left, &right),
+            (Type::Class(left), Type::Class(right)) => Rc::ptr_eq(&left, &right),
+            (Type::Instance(left), Type::Instance(right)) => Rc::ptr_eq(&left, &right),
+            (Type::Userdata(left), Type::Userdata(right)) => Rc::ptr_eq(&left, &right),
             _ => false,
         }
     }
@@ -0,0 +1,33 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// a token another thread (or a signal handler) can use to stop a running script
+// at its next evaluated expression; each interrupt stops one evaluation
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    // withdraws an interrupt that has not stopped anything yet
+    pub fn clear(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
+
+    // checks for an interrupt and consumes it
+    pub(crate) fn take(&self) -> bool {
+        self.is_interrupted() && self.flag.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,28 @@
+mod class;
+mod collect;
+mod convert;
+mod diagnostic;
+mod engine;
+mod environment;
+mod function;
+mod heap;
+mod interpreter;
+mod interrupt;
+mod native;
+mod output;
+mod parser;
+mod render;
+mod resolver;
+mod stack;
+mod tokenizer;
+mod userdata;
+
+pub use crate::convert::{FromLox, IntoLox};
+pub use crate::diagnostic::{Diagnostic, Label, Phase, Severity, Span};
+pub use crate::engine::{Lox, LoxError, Value};
+pub use crate::heap::LoxString;
+pub use crate::interpreter::Abort;
+pub use crate::interrupt::InterruptHandle;
+pub use crate::native::{Args, HostFunction, HostResult};
+pub use crate::output::CallbackWriter;
+pub use crate::userdata::UserData;
@@ -1,49 +1,91 @@
 use std::env;
-use std::fs::File;
 use std::io;
-use std::io::prelude::*;
 use std::io::Write;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-mod tokenizer;
-use crate::tokenizer::Scanner;
+use lox::{InterruptHandle, Lox, LoxError, Value};
 
-mod parser;
-use crate::parser::Parser;
+fn run_file(file_path: String) {
+    let mut lox = Lox::new();
 
-mod interpreter;
-use crate::interpreter::Interpreter;
+    match lox.run_file(file_path) {
+        Ok(_) => (),
+        Err(LoxError::Io(error)) => {
+            eprintln!("failed to read file: {}", error);
+            process::exit(74);
+        }
+        Err(LoxError::Compile(_)) => process::exit(65),
+        Err(LoxError::Runtime(_) | LoxError::Aborted(..)) => process::exit(70),
+    }
+}
 
-fn run(source: String) -> Result<(), ()> {
-    let mut scanner = Scanner::new(source);
-    let tokens = scanner.scan()?;
-    let mut parser = Parser::new(tokens);
-    let expr = parser.parse()?;
-    let _ = Interpreter::interpret(expr);
+// whether the prompt is running a line, so Ctrl-C should stop it
+static EVALUATING: AtomicBool = AtomicBool::new(false);
 
-    Ok(())
-}
+// makes Ctrl-C interrupt the running evaluation instead of killing the process,
+// and start a fresh prompt when nothing is running
+#[cfg(unix)]
+fn catch_interrupts(handle: InterruptHandle) {
+    use std::sync::OnceLock;
+    use std::{mem, ptr};
 
-fn run_file(file_path: String) {
-    let mut f = File::open(file_path).expect("failed to open file");
-    let mut buffer = String::new();
+    static HANDLE: OnceLock<InterruptHandle> = OnceLock::new();
 
-    f.read_to_string(&mut buffer)
-        .expect("failed to read file contents");
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        match HANDLE.get() {
+            Some(handle) if EVALUATING.load(Ordering::SeqCst) => handle.interrupt(),
+            // the terminal drops the half typed line, and reading carries on
+            _ => {
+                let prompt = b"\n> ";
+                unsafe {
+                    libc::write(libc::STDOUT_FILENO, prompt.as_ptr().cast(), prompt.len());
+                }
+            }
+        }
+    }
 
-    run(buffer).unwrap();
+    if HANDLE.set(handle).is_ok() {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGINT, &action, ptr::null_mut());
+        }
+    }
 }
 
+#[cfg(not(unix))]
+fn catch_interrupts(_: InterruptHandle) {}
+
 fn run_prompt() {
+    let mut lox = Lox::new();
+    let interrupt = lox.interrupt_handle();
+    catch_interrupts(interrupt.clone());
+
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
 
         let mut line = String::new();
-        io::stdin()
+        let read = io::stdin()
             .read_line(&mut line)
             .expect("failed to read line");
+        if read == 0 {
+            break;
+        }
+
+        // a Ctrl-C that landed as the last line finished should not cancel this one
+        interrupt.clear();
+        EVALUATING.store(true, Ordering::SeqCst);
+        let result = lox.eval_named("<stdin>", &line);
+        EVALUATING.store(false, Ordering::SeqCst);
 
-        let _ = run(line);
+        match result {
+            Ok(Value::Nil) | Err(_) => (),
+            Ok(value) => println!("{}", value),
+        }
     }
 }
 
@@ -0,0 +1,213 @@
+use crate::convert::{FromLox, IntoLox};
+use crate::environment::Environment;
+use crate::interpreter::Interpreter;
+use crate::output::Sink;
+use crate::parser::Type;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub type NativeBody = dyn Fn(&Args) -> Result<Type, String>;
+
+// the arguments of a native call, with typed accessors that fail with a message
+// suitable for reporting back to the script
+pub struct Args<'a> {
+    values: &'a [Type],
+}
+
+impl<'a> Args<'a> {
+    pub fn new(values: &'a [Type]) -> Self {
+        Self { values }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Result<&'a Type, String> {
+        self.values
+            .get(index)
+            .ok_or_else(|| format!("missing argument {}", index + 1))
+    }
+
+    pub fn extract<T: FromLox>(&self, index: usize) -> Result<T, String> {
+        T::from_lox(self.get(index)?)
+            .map_err(|message| format!("argument {}: {}", index + 1, message))
+    }
+
+    pub fn number(&self, index: usize) -> Result<f64, String> {
+        self.extract(index)
+    }
+
+    pub fn string(&self, index: usize) -> Result<&'a str, String> {
+        match self.get(index)? {
+            Type::String(value) => Ok(value.as_str()),
+            other => Err(format!(
+                "argument {}: expected a string, got {}",
+                index + 1,
+                type_name(other)
+            )),
+        }
+    }
+
+    pub fn bool(&self, index: usize) -> Result<bool, String> {
+        self.extract(index)
+    }
+}
+
+// the value returned by a typed host function, either plain or fallible
+pub trait HostResult {
+    fn into_result(self) -> Result<Type, String>;
+}
+
+impl<T: IntoLox> HostResult for T {
+    fn into_result(self) -> Result<Type, String> {
+        Ok(self.into_lox())
+    }
+}
+
+impl<T: IntoLox, E: fmt::Display> HostResult for Result<T, E> {
+    fn into_result(self) -> Result<Type, String> {
+        self.map(IntoLox::into_lox)
+            .map_err(|error| error.to_string())
+    }
+}
+
+// a Rust function whose parameters are converted from script values, with
+// the arity taken from its signature
+pub trait HostFunction<Params> {
+    fn arity(&self) -> usize;
+    fn call(&self, args: &Args) -> Result<Type, String>;
+}
+
+macro_rules! host_function {
+    ($($param:ident),*) => {
+        impl<F, R, $($param,)*> HostFunction<($($param,)*)> for F
+        where
+            F: Fn($($param),*) -> R,
+            R: HostResult,
+            $($param: FromLox,)*
+        {
+            fn arity(&self) -> usize {
+                <[&str]>::len(&[$(stringify!($param)),*])
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables, unused_assignments)]
+            fn call(&self, args: &Args) -> Result<Type, String> {
+                let mut index = 0;
+                $(
+                    let $param = args.extract::<$param>(index)?;
+                    index += 1;
+                )*
+                (self)($($param),*).into_result()
+            }
+        }
+    };
+}
+
+host_function!();
+host_function!(A);
+host_function!(A, B);
+host_function!(A, B, C);
+host_function!(A, B, C, D);
+host_function!(A, B, C, D, E);
+host_function!(A, B, C, D, E, G);
+
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub body: Box<NativeBody>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, arity: usize, body: F) -> Self
+    where
+        F: Fn(&Args) -> Result<Type, String> + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            arity,
+            body: Box::new(body),
+        }
+    }
+
+    pub fn call(&self, arguments: &[Type]) -> Result<Type, String> {
+        (self.body)(&Args::new(arguments))
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+fn registry(out: Sink) -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("clock", 0, |_| {
+            let elapsed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|error| error.to_string())?;
+            Ok(Type::Number(elapsed.as_secs_f64()))
+        }),
+        NativeFunction::new("print", 1, move |args| {
+            let text = Interpreter::stringify(args.get(0)?.clone());
+            writeln!(out.borrow_mut(), "{}", text).map_err(|error| error.to_string())?;
+            Ok(Type::Nil)
+        }),
+        NativeFunction::new("str", 1, |args| {
+            Ok(Type::String(
+                Interpreter::stringify(args.get(0)?.clone()).into(),
+            ))
+        }),
+        NativeFunction::new("num", 1, |args| match args.get(0)? {
+            Type::Number(value) => Ok(Type::Number(*value)),
+            Type::Bool(value) => Ok(Type::Number(if *value { 1. } else { 0. })),
+            Type::String(value) => value
+                .trim()
+                .parse::<f64>()
+                .map(Type::Number)
+                .map_err(|_| format!("can't convert \"{}\" to a number", value)),
+            other => Err(format!("can't convert {} to a number", type_name(other))),
+        }),
+        NativeFunction::new("len", 1, |args| {
+            Ok(Type::Number(args.string(0)?.chars().count() as f64))
+        }),
+        NativeFunction::new("type", 1, |args| {
+            Ok(Type::String(type_name(args.get(0)?).into()))
+        }),
+    ]
+}
+
+pub fn type_name(value: &Type) -> &'static str {
+    match value {
+        Type::Nil => "nil",
+        Type::Bool(_) => "bool",
+        Type::String(_) => "string",
+        Type::Number(_) => "number",
+        Type::Function(_) | Type::Native(_) => "function",
+        Type::Class(_) => "class",
+        Type::Instance(_) => "instance",
+        Type::Userdata(_) => "userdata",
+    }
+}
+
+pub fn define_globals(environment: &mut Environment, out: &Sink) {
+    for native in registry(Rc::clone(out)) {
+        environment.define(native.name.clone(), Type::Native(Rc::new(native)));
+    }
+}
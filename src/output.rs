@@ -0,0 +1,32 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// a writer shared between the engine's phases so it can be swapped in one place
+pub type Sink = Rc<RefCell<Box<dyn Write>>>;
+
+pub fn sink<W: Write + 'static>(writer: W) -> Sink {
+    Rc::new(RefCell::new(Box::new(writer)))
+}
+
+// adapts a closure receiving text into a `Write` sink
+pub struct CallbackWriter<F: FnMut(&str)> {
+    callback: F,
+}
+
+impl<F: FnMut(&str)> CallbackWriter<F> {
+    pub fn new(callback: F) -> Self {
+        Self { callback }
+    }
+}
+
+impl<F: FnMut(&str)> Write for CallbackWriter<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (self.callback)(&String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
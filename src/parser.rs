@@ -1,12 +1,27 @@
+use crate::class::{Class, Instance};
+use crate::diagnostic::{Diagnostic, Phase, Span};
+use crate::function::Function;
+use crate::heap::LoxString;
+use crate::native::NativeFunction;
+use crate::stack::{self, StackGuard};
 use crate::tokenizer::{CtxToken, Token};
+use crate::userdata::UserData;
+use std::cell::{Cell, RefCell};
 use std::fmt;
+use std::mem;
+use std::rc::Rc;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Type {
     Nil,
     Bool(bool),
-    String(String),
+    String(LoxString),
     Number(f64),
+    Function(Rc<Function>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    Userdata(Rc<RefCell<dyn UserData>>),
 }
 
 impl fmt::Display for Type {
@@ -14,8 +29,13 @@ impl fmt::Display for Type {
         let s = match self {
             Type::Nil => "nil".to_string(),
             Type::Bool(value) => format!("{}", value),
-            Type::String(value) => format!("\"{}\"", value.clone()),
+            Type::String(value) => format!("\"{}\"", value),
             Type::Number(value) => format!("{}", value),
+            Type::Function(function) => format!("{}", function),
+            Type::Native(native) => format!("{}", native),
+            Type::Class(class) => format!("{}", class),
+            Type::Instance(instance) => format!("{}", instance.borrow()),
+            Type::Userdata(data) => format!("{}", data.borrow()),
         };
         write!(f, "{}", s)
     }
@@ -27,82 +47,310 @@ pub enum Expr {
         left: Box<Expr>,
         operator: CtxToken,
         right: Box<Expr>,
+        span: Span,
     },
     Grouping {
         expr: Box<Expr>,
+        span: Span,
     },
     Literal {
         value: Type,
+        span: Span,
     },
     Unary {
         operator: CtxToken,
         expr: Box<Expr>,
+        span: Span,
     },
     Ternary {
         condition: Box<Expr>,
         then: Box<Expr>,
         otherwise: Box<Expr>,
+        span: Span,
+    },
+    Logical {
+        left: Box<Expr>,
+        operator: CtxToken,
+        right: Box<Expr>,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expr>,
+        paren: CtxToken,
+        arguments: Vec<Expr>,
+        span: Span,
+    },
+    Variable {
+        name: CtxToken,
+        // scope distance filled in by the resolver, None for globals
+        depth: Cell<Option<usize>>,
+        span: Span,
+    },
+    Assign {
+        name: CtxToken,
+        value: Box<Expr>,
+        depth: Cell<Option<usize>>,
+        span: Span,
+    },
+    Get {
+        object: Box<Expr>,
+        name: CtxToken,
+        span: Span,
+    },
+    Set {
+        object: Box<Expr>,
+        name: CtxToken,
+        value: Box<Expr>,
+        span: Span,
+    },
+    This {
+        keyword: CtxToken,
+        depth: Cell<Option<usize>>,
+        span: Span,
+    },
+    Super {
+        keyword: CtxToken,
+        method: CtxToken,
+        depth: Cell<Option<usize>>,
+        span: Span,
     },
 }
 
 impl Expr {
-    fn to_string(&self) -> String {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary { span, .. }
+            | Expr::Grouping { span, .. }
+            | Expr::Literal { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Ternary { span, .. }
+            | Expr::Logical { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Variable { span, .. }
+            | Expr::Assign { span, .. }
+            | Expr::Get { span, .. }
+            | Expr::Set { span, .. }
+            | Expr::This { span, .. }
+            | Expr::Super { span, .. } => *span,
+        }
+    }
+
+    // what an operator, call or property access applies to on its left. a chain
+    // such as `a + b + c` or `a.b().c` nests to the left as deeply as it is
+    // long, so later phases walk it in a loop rather than recursively
+    pub fn chained(&self) -> Option<&Expr> {
+        match self {
+            Expr::Binary { left, .. } | Expr::Logical { left, .. } => Some(left),
+            Expr::Call { callee, .. } => Some(callee),
+            Expr::Get { object, .. } => Some(object),
+            _ => None,
+        }
+    }
+
+    // moves a subexpression out, leaving `nil` in its place
+    fn take(expr: &mut Box<Expr>) -> Box<Expr> {
+        let span = expr.span();
+        mem::replace(
+            expr,
+            Box::new(Expr::Literal {
+                value: Type::Nil,
+                span,
+            }),
+        )
+    }
+
+    // detaches the rest of a chain from its last link
+    fn take_chain(&mut self) -> Option<Box<Expr>> {
+        match self {
+            Expr::Binary { left: inner, .. }
+            | Expr::Logical { left: inner, .. }
+            | Expr::Call { callee: inner, .. }
+            | Expr::Get { object: inner, .. } => {
+                inner.chained().is_some().then(|| Expr::take(inner))
+            }
+            _ => None,
+        }
+    }
+}
+
+// a long chain is dropped one link at a time
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut chain = self.take_chain();
+        while let Some(mut link) = chain {
+            chain = link.take_chain();
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expr::Binary {
                 left,
                 operator,
                 right,
-            } => format!(
+                ..
+            } => write!(
+                f,
                 "({} {} {})",
-                left.to_string(),
+                left,
                 operator.get_token().get_lexeme(),
-                right.to_string()
+                right
             ),
-            Expr::Grouping { expr } => format!("({})", expr.to_string()),
-            Expr::Literal { value } => format!("{}", value),
-            Expr::Unary { operator, expr } => format!(
-                "({} {})",
+            Expr::Logical {
+                left,
+                operator,
+                right,
+                ..
+            } => write!(
+                f,
+                "({} {} {})",
+                left,
                 operator.get_token().get_lexeme(),
-                expr.to_string()
+                right
             ),
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Grouping { expr, .. } => write!(f, "({})", expr),
+            Expr::Literal { value, .. } => write!(f, "{}", value),
+            Expr::Unary { operator, expr, .. } => {
+                write!(f, "({} {})", operator.get_token().get_lexeme(), expr)
+            }
             Expr::Ternary {
                 condition,
                 then,
                 otherwise,
-            } => format!(
-                "({} ? {} : {})",
-                condition.to_string(),
-                then.to_string(),
-                otherwise.to_string(),
+                ..
+            } => write!(f, "({} ? {} : {})", condition, then, otherwise),
+            Expr::Variable { name, .. } => write!(f, "{}", name.get_token().get_lexeme()),
+            Expr::Assign { name, value, .. } => {
+                write!(f, "(= {} {})", name.get_token().get_lexeme(), value)
+            }
+            Expr::Get { object, name, .. } => {
+                write!(f, "(. {} {})", object, name.get_token().get_lexeme())
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => write!(
+                f,
+                "(= (. {} {}) {})",
+                object,
+                name.get_token().get_lexeme(),
+                value
             ),
+            Expr::This { .. } => write!(f, "this"),
+            Expr::Super { method, .. } => {
+                write!(f, "(super {})", method.get_token().get_lexeme())
+            }
         }
     }
 }
 
-impl fmt::Display for Expr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_string())
-    }
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expression {
+        expr: Expr,
+    },
+    Print {
+        expr: Expr,
+    },
+    Var {
+        name: CtxToken,
+        initializer: Option<Expr>,
+    },
+    Block {
+        statements: Vec<Stmt>,
+    },
+    If {
+        condition: Expr,
+        then: Box<Stmt>,
+        otherwise: Option<Box<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    Function {
+        declaration: Rc<FunctionDecl>,
+    },
+    Return {
+        keyword: CtxToken,
+        value: Option<Expr>,
+    },
+    Class {
+        name: CtxToken,
+        superclass: Option<Expr>,
+        methods: Vec<Rc<FunctionDecl>>,
+    },
 }
 
+#[derive(Debug)]
+pub struct FunctionDecl {
+    pub name: CtxToken,
+    pub params: Vec<CtxToken>,
+    pub body: Vec<Stmt>,
+}
+
+const MAX_ARGUMENTS: usize = 255;
+// how deeply groupings, blocks and unary operators may nest; a chain of binary
+// operators, calls or property accesses is parsed in a loop and counts once
+pub const DEFAULT_MAX_NESTING: usize = 128;
+
 pub struct Parser {
     current: usize,
     tokens: Vec<CtxToken>,
+    diagnostics: Vec<Diagnostic>,
+    // how deeply statements and expressions are nested at the current token
+    depth: usize,
+    max_nesting: usize,
+    stack: StackGuard,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<CtxToken>) -> Self {
         Self {
             current: 0,
-            tokens: tokens,
+            tokens,
+            diagnostics: Vec::new(),
+            depth: 0,
+            max_nesting: DEFAULT_MAX_NESTING,
+            stack: StackGuard::new(stack::DEFAULT_STACK_LIMIT),
         }
     }
 
-    fn error(&self, message: &str) {
-        let token = self
-            .get_current()
-            .unwrap_or_else(|| self.tokens.last().unwrap().clone());
-        eprintln!("ERROR PARSER {}: {}", token, message);
+    pub fn with_max_nesting(mut self, max_nesting: usize) -> Self {
+        self.max_nesting = max_nesting;
+        self
+    }
+
+    pub fn with_stack_limit(mut self, limit: usize) -> Self {
+        self.stack = StackGuard::new(limit);
+        self
+    }
+
+    fn error<T>(&mut self, message: &str) -> Result<T, ()> {
+        let span = self.current_span();
+        self.diagnostics
+            .push(Diagnostic::error(Phase::Parser, message, span));
+        Err(())
+    }
+
+    fn current_span(&self) -> Span {
+        match self.get_current() {
+            Some(token) => token.span(),
+            None => self.tokens.last().unwrap().span(),
+        }
     }
 
     fn get_current(&self) -> Option<CtxToken> {
@@ -113,24 +361,92 @@ impl Parser {
         self.current += 1;
     }
 
+    fn previous(&self) -> CtxToken {
+        self.tokens[self.current - 1].clone()
+    }
+
+    fn check(&self, token: Token) -> bool {
+        match self.get_current() {
+            Some(ctx_token) => ctx_token.get_token() == token,
+            None => false,
+        }
+    }
+
+    fn advance_if(&mut self, token: Token) -> bool {
+        match self.check(token) {
+            true => {
+                self.advance();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        match self.get_current() {
+            Some(token) => token.get_token() == Token::Eof,
+            None => true,
+        }
+    }
+
     fn consume(&mut self, token: Token, message: &str) -> Result<(), ()> {
         match self.get_current() {
-            Some(ctx_token) => {
-                return if ctx_token.get_token() == token {
+            Some(ctx_token) if ctx_token.get_token() == token => {
+                self.advance();
+                Ok(())
+            }
+            _ => self.error(message),
+        }
+    }
+
+    fn consume_identifier(&mut self, message: &str) -> Result<CtxToken, ()> {
+        match self.get_current() {
+            Some(ctx_token) => match ctx_token.get_token() {
+                Token::Identifier(_) => {
                     self.advance();
-                    Ok(())
-                } else {
-                    Err(self.error(message))
+                    Ok(ctx_token)
                 }
-            }
-            _ => Err(self.error(message)),
+                _ => self.error(message),
+            },
+            _ => self.error(message),
         }
     }
 
-    #[allow(unused)]
+    // every phase after this one walks the tree recursively, so input nested
+    // deeply enough to overflow the native stack is rejected here
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ()>) -> Result<T, ()> {
+        if self.depth < self.max_nesting && !self.stack.is_exhausted() {
+            self.depth += 1;
+            let result = parse(self);
+            self.depth -= 1;
+            return result;
+        }
+
+        let note = match self.depth >= self.max_nesting {
+            true => format!("nesting is limited to {} levels", self.max_nesting),
+            false => format!(
+                "parsing is limited to {} KiB of native stack",
+                self.stack.limit() / 1024
+            ),
+        };
+
+        let span = self.current_span();
+        self.diagnostics
+            .push(Diagnostic::error(Phase::Parser, "stack overflow", span).with_note(note));
+        Err(())
+    }
+
+    // skips to the next likely statement boundary after a syntax error
     fn synchronize(&mut self) {
+        // braces opened while skipping, whose contents are skipped along with them
+        let mut braces = 0;
         while let Some(token) = self.get_current() {
             match token.get_token() {
+                Token::LeftBrace => braces += 1,
+                Token::RightBrace if braces > 0 => braces -= 1,
+                // left for the enclosing block to close
+                Token::RightBrace => return,
+                _ if braces > 0 => (),
                 Token::SemiColon => {
                     self.advance();
                     return;
@@ -145,57 +461,384 @@ impl Parser {
                 | Token::Return => {
                     return;
                 }
-                _ => self.advance(),
+                _ => (),
             }
+            self.advance();
         }
     }
 }
 
 macro_rules! right_recurse {
-    ($func_name:ident, $toks:pat, $higher_prec:ident) => (
+    ($func_name:ident, $toks:pat, $higher_prec:ident) => {
+        right_recurse!($func_name, $toks, $higher_prec, Binary);
+    };
+    ($func_name:ident, $toks:pat, $higher_prec:ident, $variant:ident) => {
         fn $func_name(&mut self) -> Result<Expr, ()> {
-            let mut expr = self.$higher_prec()?;
+            let Ok(first) = self.$higher_prec() else {
+                return Err(());
+            };
+            self.operator_chain(
+                first,
+                |token| matches!(token, $toks),
+                Parser::$higher_prec,
+                |left, operator, right| Expr::$variant {
+                    span: left.span().to(right.span()),
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                },
+            )
+        }
+    };
+}
 
-            while let Some(token) = self.get_current() {
-                match token.get_token() {
-                    $toks => {
+impl Parser {
+    // keeps going after syntax errors, so the statements that did parse are
+    // returned alongside every error found
+    pub fn parse(&mut self) -> (Vec<Stmt>, Vec<Diagnostic>) {
+        self.stack = StackGuard::new(self.stack.limit());
+        let statements = self.declarations(false);
+
+        (statements, std::mem::take(&mut self.diagnostics))
+    }
+
+    // parses declarations up to the end of the input, or of the block being
+    // parsed, recovering after each one that has a syntax error so the rest are
+    // still parsed and checked
+    fn declarations(&mut self, in_block: bool) -> Vec<Stmt> {
+        let mut statements: Vec<Stmt> = Vec::new();
+
+        while !(self.is_at_end() || in_block && self.check(Token::RightBrace)) {
+            let start = self.current;
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(()) => {
+                    self.synchronize();
+                    // a declaration that failed on its first token would only fail
+                    // there again, and outside a block a stray '}' has already been
+                    // reported and closes nothing
+                    if self.current == start || (!in_block && self.check(Token::RightBrace)) {
                         self.advance();
-                        expr = Expr::Binary {
-                           left: Box::new(expr.clone()),
-                           operator: token.clone(),
-                           right: Box::new(self.$higher_prec()?.clone()),
-                        }
-                    },
-                    _ => break,
+                        self.synchronize();
+                    }
                 }
             }
+        }
+
+        statements
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, ()> {
+        match self.get_current().map(|token| token.get_token()) {
+            Some(Token::Var) => {
+                self.advance();
+                self.var_declaration()
+            }
+            Some(Token::Fun) => {
+                self.advance();
+                Ok(Stmt::Function {
+                    declaration: Rc::new(self.function("function")?),
+                })
+            }
+            Some(Token::Class) => {
+                self.advance();
+                self.class_declaration()
+            }
+            _ => self.statement(),
+        }
+    }
 
-            Ok(expr)
+    fn class_declaration(&mut self) -> Result<Stmt, ()> {
+        let name = self.consume_identifier("expected class name")?;
+
+        let superclass = match self.advance_if(Token::Less) {
+            true => {
+                let name = self.consume_identifier("expected superclass name")?;
+                Some(Expr::Variable {
+                    span: name.span(),
+                    name,
+                    depth: Cell::new(None),
+                })
+            }
+            false => None,
+        };
+
+        self.consume(Token::LeftBrace, "expected '{' before class body")?;
+
+        let mut methods: Vec<Rc<FunctionDecl>> = Vec::new();
+        while !self.is_at_end() && !self.check(Token::RightBrace) {
+            methods.push(Rc::new(self.function("method")?));
         }
-    )
-}
 
-impl Parser {
-    pub fn parse(&mut self) -> Result<Expr, ()> {
-        self.expression()
+        self.consume(Token::RightBrace, "expected '}' after class body")?;
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    fn function(&mut self, kind: &str) -> Result<FunctionDecl, ()> {
+        let name = self.consume_identifier(&format!("expected {} name", kind))?;
+        self.consume(
+            Token::LeftParen,
+            &format!("expected '(' after {} name", kind),
+        )?;
+
+        let mut params: Vec<CtxToken> = Vec::new();
+        if !self.check(Token::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return self.error("can't have more than 255 parameters");
+                }
+                params.push(self.consume_identifier("expected parameter name")?);
+
+                if !self.advance_if(Token::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(Token::RightParen, "expected ')' after parameters")?;
+
+        self.consume(
+            Token::LeftBrace,
+            &format!("expected '{{' before {} body", kind),
+        )?;
+        let body = self.block()?;
+
+        Ok(FunctionDecl { name, params, body })
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, ()> {
+        let name = self.consume_identifier("expected variable name")?;
+
+        let initializer = match self.get_current().map(|token| token.get_token()) {
+            Some(Token::Equal) => {
+                self.advance();
+                Some(self.expression()?)
+            }
+            _ => None,
+        };
+
+        self.consume(Token::SemiColon, "expected ';' after variable declaration")?;
+        Ok(Stmt::Var { name, initializer })
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ()> {
+        self.nested(Parser::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> Result<Stmt, ()> {
+        match self.get_current().map(|token| token.get_token()) {
+            Some(Token::Print) => {
+                self.advance();
+                self.print_statement()
+            }
+            Some(Token::LeftBrace) => {
+                self.advance();
+                Ok(Stmt::Block {
+                    statements: self.block()?,
+                })
+            }
+            Some(Token::If) => {
+                self.advance();
+                self.if_statement()
+            }
+            Some(Token::While) => {
+                self.advance();
+                self.while_statement()
+            }
+            Some(Token::For) => {
+                self.advance();
+                self.for_statement()
+            }
+            Some(Token::Return) => {
+                self.advance();
+                self.return_statement()
+            }
+            _ => self.expression_statement(),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ()> {
+        self.consume(Token::LeftParen, "expected '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(Token::RightParen, "expected ')' after if condition")?;
+
+        let then = Box::new(self.statement()?);
+        // an else binds to the nearest preceding if
+        let otherwise = match self.advance_if(Token::Else) {
+            true => Some(Box::new(self.statement()?)),
+            false => None,
+        };
+
+        Ok(Stmt::If {
+            condition,
+            then,
+            otherwise,
+        })
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, ()> {
+        let keyword = self.previous();
+
+        let value = match self.check(Token::SemiColon) {
+            true => None,
+            false => Some(self.expression()?),
+        };
+        self.consume(Token::SemiColon, "expected ';' after return value")?;
+
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, ()> {
+        self.consume(Token::LeftParen, "expected '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(Token::RightParen, "expected ')' after condition")?;
+        let body = Box::new(self.statement()?);
+
+        Ok(Stmt::While { condition, body })
+    }
+
+    // desugars into an equivalent while loop wrapped in blocks
+    fn for_statement(&mut self) -> Result<Stmt, ()> {
+        self.consume(Token::LeftParen, "expected '(' after 'for'")?;
+
+        let initializer = match self.get_current().map(|token| token.get_token()) {
+            Some(Token::SemiColon) => {
+                self.advance();
+                None
+            }
+            Some(Token::Var) => {
+                self.advance();
+                Some(self.var_declaration()?)
+            }
+            _ => Some(self.expression_statement()?),
+        };
+
+        let condition = match self.check(Token::SemiColon) {
+            true => Expr::Literal {
+                value: Type::Bool(true),
+                span: self.get_current().map(|token| token.span()).unwrap(),
+            },
+            false => self.expression()?,
+        };
+        self.consume(Token::SemiColon, "expected ';' after loop condition")?;
+
+        let increment = match self.check(Token::RightParen) {
+            true => None,
+            false => Some(self.expression()?),
+        };
+        self.consume(Token::RightParen, "expected ')' after for clauses")?;
+
+        let mut body = self.statement()?;
+
+        if let Some(increment) = increment {
+            body = Stmt::Block {
+                statements: vec![body, Stmt::Expression { expr: increment }],
+            };
+        }
+
+        body = Stmt::While {
+            condition,
+            body: Box::new(body),
+        };
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block {
+                statements: vec![initializer, body],
+            };
+        }
+
+        Ok(body)
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ()> {
+        self.nested(|parser| {
+            // recovering here keeps the enclosing declaration after a mistake in its
+            // body, rather than leaving its closing '}' to be misreported
+            let statements = parser.declarations(true);
+            parser.consume(Token::RightBrace, "expected '}' after block")?;
+            Ok(statements)
+        })
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, ()> {
+        let expr = self.expression()?;
+        self.consume(Token::SemiColon, "expected ';' after value")?;
+        Ok(Stmt::Print { expr })
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, ()> {
+        let expr = self.expression()?;
+        self.consume(Token::SemiColon, "expected ';' after expression")?;
+        Ok(Stmt::Expression { expr })
     }
 
     fn expression(&mut self) -> Result<Expr, ()> {
-        self.ternary()
+        self.nested(Parser::assignment)
+    }
+
+    // every expression nested in parentheses recurses through here, so each of
+    // these steps leaves the work after its first operand to another method,
+    // keeping the frames that stay on the stack small (`?` costs more of it in
+    // unoptimised builds than a plain `let else`)
+    fn assignment(&mut self) -> Result<Expr, ()> {
+        let Ok(expr) = self.ternary() else {
+            return Err(());
+        };
+        self.finish_assignment(expr)
+    }
+
+    fn finish_assignment(&mut self, expr: Expr) -> Result<Expr, ()> {
+        match self.get_current().map(|token| token.get_token()) {
+            Some(Token::Equal) => {
+                let equals = self.current;
+                self.advance();
+                let value = self.nested(Parser::assignment)?;
+
+                let span = expr.span().to(value.span());
+                let mut target = expr;
+                match &mut target {
+                    Expr::Variable { name, .. } => Ok(Expr::Assign {
+                        name: name.clone(),
+                        value: Box::new(value),
+                        depth: Cell::new(None),
+                        span,
+                    }),
+                    Expr::Get { object, name, .. } => Ok(Expr::Set {
+                        object: Expr::take(object),
+                        name: name.clone(),
+                        value: Box::new(value),
+                        span,
+                    }),
+                    _ => {
+                        self.current = equals;
+                        self.error("invalid assignment target")
+                    }
+                }
+            }
+            _ => Ok(expr),
+        }
     }
 
     fn ternary(&mut self) -> Result<Expr, ()> {
-        let expr = self.equality()?;
+        let Ok(expr) = self.logic_or() else {
+            return Err(());
+        };
+        self.finish_ternary(expr)
+    }
 
+    fn finish_ternary(&mut self, expr: Expr) -> Result<Expr, ()> {
         match self.get_current() {
             Some(token) => match token.get_token() {
                 Token::Quest => {
                     self.advance();
                     let then = self.expression()?;
-                    let _ =
-                        self.consume(Token::Colon, "expected colon inside ternary expression")?;
+                    self.consume(Token::Colon, "expected colon inside ternary expression")?;
                     let otherwise = self.expression()?;
                     Ok(Expr::Ternary {
+                        span: expr.span().to(otherwise.span()),
                         condition: Box::new(expr),
                         then: Box::new(then),
                         otherwise: Box::new(otherwise),
@@ -207,64 +850,160 @@ impl Parser {
         }
     }
 
+    right_recurse!(logic_or, Token::Or, logic_and, Logical);
+    right_recurse!(logic_and, Token::And, equality, Logical);
     right_recurse!(equality, Token::EqualEqual | Token::BangEqual, comparison);
-    right_recurse!(comparison, Token::Less | Token::LessEqual | Token::Greater | Token::GreaterEqual, term);
+    right_recurse!(
+        comparison,
+        Token::Less | Token::LessEqual | Token::Greater | Token::GreaterEqual,
+        term
+    );
     right_recurse!(term, Token::Plus | Token::Minus, factor);
     right_recurse!(factor, Token::Slash | Token::Star, unary);
 
     fn unary(&mut self) -> Result<Expr, ()> {
-        match self.get_current() {
-            Some(token) => match token.get_token() {
-                Token::Bang | Token::Minus => {
-                    self.advance();
-                    Ok(Expr::Unary {
-                        operator: token.clone(),
-                        expr: Box::new(self.unary()?.clone()),
-                    })
+        match self.check(Token::Bang) || self.check(Token::Minus) {
+            true => self.unary_operator(),
+            false => self.call(),
+        }
+    }
+
+    fn unary_operator(&mut self) -> Result<Expr, ()> {
+        let operator = self.get_current().unwrap();
+        self.advance();
+        let expr = self.nested(Parser::unary)?;
+        Ok(Expr::Unary {
+            span: operator.span().to(expr.span()),
+            operator,
+            expr: Box::new(expr),
+        })
+    }
+
+    fn call(&mut self) -> Result<Expr, ()> {
+        let Ok(expr) = self.primary() else {
+            return Err(());
+        };
+        self.call_chain(expr)
+    }
+
+    // counts as one level of nesting however long it is, like an operator chain
+    fn call_chain(&mut self, mut expr: Expr) -> Result<Expr, ()> {
+        loop {
+            if self.advance_if(Token::LeftParen) {
+                expr = self.finish_call(expr)?;
+            } else if self.advance_if(Token::Dot) {
+                let name = self.consume_identifier("expected property name after '.'")?;
+                expr = Expr::Get {
+                    span: expr.span().to(name.span()),
+                    object: Box::new(expr),
+                    name,
+                };
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ()> {
+        let mut arguments: Vec<Expr> = Vec::new();
+
+        if !self.check(Token::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return self.error("can't have more than 255 arguments");
                 }
-                _ => self.primary(),
-            },
-            _ => self.primary(),
+                arguments.push(self.expression()?);
+
+                if !self.advance_if(Token::Comma) {
+                    break;
+                }
+            }
         }
+        self.consume(Token::RightParen, "expected ')' after arguments")?;
+
+        let paren = self.previous();
+        Ok(Expr::Call {
+            span: callee.span().to(paren.span()),
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
     }
 
     fn primary(&mut self) -> Result<Expr, ()> {
+        match self.check(Token::LeftParen) {
+            true => self.grouping(),
+            false => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, ()> {
         match self.get_current() {
             Some(token) => match token.get_token() {
                 Token::False => {
                     self.advance();
                     Ok(Expr::Literal {
                         value: Type::Bool(false),
+                        span: token.span(),
                     })
                 }
                 Token::True => {
                     self.advance();
                     Ok(Expr::Literal {
                         value: Type::Bool(true),
+                        span: token.span(),
                     })
                 }
                 Token::Nil => {
                     self.advance();
-                    Ok(Expr::Literal { value: Type::Nil })
+                    Ok(Expr::Literal {
+                        value: Type::Nil,
+                        span: token.span(),
+                    })
                 }
                 Token::Number(value) => {
                     self.advance();
                     Ok(Expr::Literal {
                         value: Type::Number(value),
+                        span: token.span(),
                     })
                 }
                 Token::String(value) => {
                     self.advance();
                     Ok(Expr::Literal {
-                        value: Type::String(value.clone()),
+                        value: Type::String(LoxString::new(value.clone())),
+                        span: token.span(),
                     })
                 }
-                Token::LeftParen => {
+                Token::Super => {
                     self.advance();
-                    let expr = Box::new(self.expression()?.clone());
-                    let _ = self.consume(Token::RightParen, "missing closing paren")?;
-
-                    Ok(Expr::Grouping { expr: expr })
+                    self.consume(Token::Dot, "expected '.' after 'super'")?;
+                    let method = self.consume_identifier("expected superclass method name")?;
+                    Ok(Expr::Super {
+                        span: token.span().to(method.span()),
+                        keyword: token,
+                        method,
+                        depth: Cell::new(None),
+                    })
+                }
+                Token::This => {
+                    self.advance();
+                    Ok(Expr::This {
+                        span: token.span(),
+                        keyword: token,
+                        depth: Cell::new(None),
+                    })
+                }
+                // outside statement position `print` names the native function
+                Token::Identifier(_) | Token::Print => {
+                    self.advance();
+                    Ok(Expr::Variable {
+                        span: token.span(),
+                        name: token,
+                        depth: Cell::new(None),
+                    })
                 }
                 Token::EqualEqual
                 | Token::BangEqual
@@ -272,10 +1011,51 @@ impl Parser {
                 | Token::LessEqual
                 | Token::Greater
                 | Token::GreaterEqual
-                | Token::Plus => Err(self.error("missing expression on left side of operator")),
-                _ => Err(self.error("missing expression")),
+                | Token::Plus => self.error("missing expression on left side of operator"),
+                _ => self.error("missing expression"),
             },
-            _ => Err(self.error("missing expression")),
+            _ => self.error("missing expression"),
         }
     }
+
+    fn grouping(&mut self) -> Result<Expr, ()> {
+        let start = self.current;
+        self.advance();
+        let Ok(expr) = self.expression() else {
+            return Err(());
+        };
+        self.finish_grouping(start, expr)
+    }
+
+    fn finish_grouping(&mut self, start: usize, expr: Expr) -> Result<Expr, ()> {
+        self.consume(Token::RightParen, "missing closing paren")?;
+
+        Ok(Expr::Grouping {
+            expr: Box::new(expr),
+            span: self.tokens[start].span().to(self.previous().span()),
+        })
+    }
+
+    // the rest of a chain of left-associative operators, each taking its right
+    // operand from `operand`
+    fn operator_chain(
+        &mut self,
+        mut expr: Expr,
+        is_operator: fn(&Token) -> bool,
+        operand: fn(&mut Parser) -> Result<Expr, ()>,
+        link: fn(Expr, CtxToken, Expr) -> Expr,
+    ) -> Result<Expr, ()> {
+        // later phases walk a chain of these in a loop, so however long it is it
+        // only counts as one level of nesting
+        while let Some(token) = self.get_current() {
+            if !is_operator(&token.get_token()) {
+                break;
+            }
+            self.advance();
+            let right = operand(self)?;
+            expr = link(expr, token, right);
+        }
+
+        Ok(expr)
+    }
 }
@@ -0,0 +1,270 @@
+use crate::diagnostic::{Diagnostic, Severity, Span};
+use crate::parser::{FunctionDecl, Stmt};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::{Rc, Weak};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+struct Source {
+    name: String,
+    text: String,
+    // the functions declared in this source, which can raise errors in it later
+    functions: Vec<Weak<FunctionDecl>>,
+}
+
+// one underline drawn beneath a line of source
+struct Mark<'a> {
+    // whitespace up to the first underlined char, keeping tabs so columns line up
+    indent: String,
+    width: usize,
+    marker: char,
+    style: &'static str,
+    message: Option<&'a str>,
+}
+
+// renders diagnostics against the sources their spans point into; once a newer
+// source is added, an older one is kept only while a function declared in it
+// is still alive
+pub struct Renderer {
+    sources: HashMap<usize, Source>,
+    next_id: usize,
+    color: bool,
+}
+
+impl Renderer {
+    pub fn new(color: bool) -> Self {
+        Self {
+            sources: HashMap::new(),
+            next_id: 0,
+            color,
+        }
+    }
+
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    // the returned id is what the scanner should stamp on its spans
+    pub fn add_source(&mut self, name: &str, text: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.sources.insert(
+            id,
+            Source {
+                name: name.to_string(),
+                text: text.to_string(),
+                functions: Vec::new(),
+            },
+        );
+        id
+    }
+
+    // records the functions a parsed source declares, so it outlives the run
+    pub fn retain(&mut self, id: usize, statements: &[Stmt]) {
+        if let Some(source) = self.sources.get_mut(&id) {
+            declared_functions(statements, &mut source.functions);
+        }
+    }
+
+    // forgets the sources no living function was declared in, so a long session
+    // only holds on to code that can still raise errors
+    pub fn prune(&mut self) {
+        self.sources.retain(|_, source| {
+            source
+                .functions
+                .iter()
+                .any(|function| function.strong_count() > 0)
+        });
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let (severity, accent) = match diagnostic.severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+        let phase = format!("{:?}", diagnostic.phase).to_lowercase();
+
+        let mut lines = vec![format!(
+            "{}{}",
+            self.paint(accent, &format!("{}[{}]", severity, phase)),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        )];
+
+        let span = diagnostic.span;
+        let source = match self.sources.get(&span.source) {
+            Some(source) if span.end <= source.text.len() => source,
+            _ => {
+                lines.push(format!("  at {}:{}", span.line, span.column));
+                self.render_notes(&mut lines, diagnostic, 1);
+                return lines.join("\n");
+            }
+        };
+
+        // source line number -> (line text, underlines beneath it)
+        let mut rows: BTreeMap<usize, (&str, Vec<Mark>)> = BTreeMap::new();
+        self.mark(&mut rows, &source.text, span, '^', accent, None);
+        for label in &diagnostic.labels {
+            if label.span.source == span.source && label.span.end <= source.text.len() {
+                let message = Some(label.message.as_str());
+                self.mark(&mut rows, &source.text, label.span, '-', BLUE, message);
+            }
+        }
+
+        let gutter = rows.keys().last().map_or(1, |line| line.to_string().len());
+        let blank = " ".repeat(gutter);
+
+        lines.push(format!(
+            "{}{} {}:{}:{}",
+            blank,
+            self.paint(BLUE, "-->"),
+            source.name,
+            span.line,
+            span.column
+        ));
+        lines.push(format!("{} {}", blank, self.paint(BLUE, "|")));
+
+        let mut previous = None;
+        for (line, (text, marks)) in &rows {
+            if previous.is_some_and(|previous| line - previous > 1) {
+                lines.push(self.paint(BLUE, "..."));
+            }
+            previous = Some(*line);
+
+            let number = format!("{:>width$} |", line, width = gutter);
+            lines.push(format!("{} {}", self.paint(BLUE, &number), text));
+            for mark in marks {
+                let underline = mark.marker.to_string().repeat(mark.width);
+                let underline = match mark.message {
+                    Some(message) => format!("{} {}", underline, message),
+                    None => underline,
+                };
+                lines.push(format!(
+                    "{} {} {}{}",
+                    blank,
+                    self.paint(BLUE, "|"),
+                    mark.indent,
+                    self.paint(mark.style, &underline)
+                ));
+            }
+        }
+
+        self.render_notes(&mut lines, diagnostic, gutter);
+        lines.join("\n")
+    }
+
+    // underlines `span` on every line it covers, with the message on the last one
+    fn mark<'a>(
+        &self,
+        rows: &mut BTreeMap<usize, (&'a str, Vec<Mark<'a>>)>,
+        text: &'a str,
+        span: Span,
+        marker: char,
+        style: &'static str,
+        message: Option<&'a str>,
+    ) {
+        let mut line = span.line;
+        let mut position = span.start;
+
+        loop {
+            let line_start = text[..position].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = text[position..]
+                .find('\n')
+                .map_or(text.len(), |i| position + i);
+            let last = span.end <= line_end + 1 || line_end == text.len();
+
+            let indent = text[line_start..position]
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let width = text[position..span.end.min(line_end)].chars().count();
+
+            let (_, marks) = rows.entry(line).or_insert((
+                text[line_start..line_end].trim_end_matches('\r'),
+                Vec::new(),
+            ));
+            marks.push(Mark {
+                indent,
+                width: width.max(1),
+                marker,
+                style,
+                message: if last { message } else { None },
+            });
+
+            if last {
+                break;
+            }
+            position = line_end + 1;
+            line += 1;
+        }
+    }
+
+    // the call stack, then any notes
+    fn render_notes(&self, lines: &mut Vec<String>, diagnostic: &Diagnostic, gutter: usize) {
+        for frame in &diagnostic.trace {
+            let call_site = frame.call_site;
+            let location = match self.sources.get(&call_site.source) {
+                Some(source) => format!("{}:{}:{}", source.name, call_site.line, call_site.column),
+                None => format!("{}:{}", call_site.line, call_site.column),
+            };
+            lines.push(format!(
+                "{} {} in {}() called at {}",
+                " ".repeat(gutter),
+                self.paint(BLUE, "="),
+                frame.function,
+                location
+            ));
+        }
+        for note in &diagnostic.notes {
+            lines.push(format!(
+                "{} {} {} {}",
+                " ".repeat(gutter),
+                self.paint(BLUE, "="),
+                self.paint(BOLD, "note:"),
+                note
+            ));
+        }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+fn declared_functions(statements: &[Stmt], functions: &mut Vec<Weak<FunctionDecl>>) {
+    for statement in statements {
+        match statement {
+            Stmt::Block { statements } => declared_functions(statements, functions),
+            Stmt::If {
+                then, otherwise, ..
+            } => {
+                declared_functions(std::slice::from_ref(then), functions);
+                if let Some(otherwise) = otherwise {
+                    declared_functions(std::slice::from_ref(otherwise), functions);
+                }
+            }
+            Stmt::While { body, .. } => declared_functions(std::slice::from_ref(body), functions),
+            Stmt::Function { declaration } => {
+                functions.push(Rc::downgrade(declaration));
+                declared_functions(&declaration.body, functions);
+            }
+            Stmt::Class { methods, .. } => {
+                for method in methods {
+                    functions.push(Rc::downgrade(method));
+                    declared_functions(&method.body, functions);
+                }
+            }
+            Stmt::Expression { .. }
+            | Stmt::Print { .. }
+            | Stmt::Var { .. }
+            | Stmt::Return { .. } => (),
+        }
+    }
+}
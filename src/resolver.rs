@@ -0,0 +1,315 @@
+use crate::diagnostic::{Diagnostic, Phase, Span};
+use crate::parser::{Expr, FunctionDecl, Stmt};
+use crate::tokenizer::CtxToken;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Clone, Copy)]
+struct Binding {
+    // whether the initializer has finished
+    defined: bool,
+    declared_at: Span,
+}
+
+pub struct Resolver {
+    scopes: Vec<HashMap<String, Binding>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, token: &CtxToken, message: &str) {
+        self.diagnostics
+            .push(Diagnostic::error(Phase::Resolver, message, token.span()));
+    }
+
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Result<(), Vec<Diagnostic>> {
+        self.resolve_statements(statements);
+
+        if self.diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.diagnostics))
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &CtxToken) {
+        let binding = Binding {
+            defined: false,
+            declared_at: name.span(),
+        };
+
+        let previous = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.get_token().get_lexeme(), binding),
+            None => None,
+        };
+
+        if let Some(previous) = previous {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    Phase::Resolver,
+                    "already a variable with this name in this scope",
+                    name.span(),
+                )
+                .with_label(previous.declared_at, "first declared here"),
+            );
+        }
+    }
+
+    fn define(&mut self, name: &CtxToken) {
+        self.define_name(name.get_token().get_lexeme(), name.span());
+    }
+
+    fn define_name(&mut self, name: String, span: Span) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let binding = scope.entry(name).or_insert(Binding {
+                defined: true,
+                declared_at: span,
+            });
+            binding.defined = true;
+        }
+    }
+
+    fn resolve_local(&mut self, name: &CtxToken, depth: &Cell<Option<usize>>) {
+        let lexeme = name.get_token().get_lexeme();
+
+        // names not found in any local scope are left unresolved and treated as globals
+        depth.set(
+            self.scopes
+                .iter()
+                .rev()
+                .position(|scope| scope.contains_key(&lexeme)),
+        );
+    }
+
+    fn resolve_statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression { expr } | Stmt::Print { expr } => self.resolve_expr(expr),
+            Stmt::Var { name, initializer } => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.resolve_statement(otherwise);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.resolve_expr(condition);
+                self.resolve_statement(body);
+            }
+            Stmt::Function { declaration } => {
+                // defined eagerly so the function can refer to itself recursively
+                self.declare(&declaration.name);
+                self.define(&declaration.name);
+                self.resolve_function(declaration, FunctionType::Function);
+            }
+            Stmt::Return { keyword, value } => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "can't return from top-level code");
+                }
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(keyword, "can't return a value from an initializer");
+                    }
+                    self.resolve_expr(value);
+                }
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable {
+                        name: superclass_name,
+                        ..
+                    } = superclass
+                    {
+                        if superclass_name.get_token() == name.get_token() {
+                            self.error(superclass_name, "a class can't inherit from itself");
+                        }
+                    }
+
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expr(superclass);
+
+                    // methods of a subclass close over a scope holding `super`
+                    self.begin_scope();
+                    self.define_name("super".to_string(), name.span());
+                }
+
+                self.begin_scope();
+                self.define_name("this".to_string(), name.span());
+
+                for method in methods {
+                    let function_type = match method.name.get_token().get_lexeme().as_str() {
+                        "init" => FunctionType::Initializer,
+                        _ => FunctionType::Method,
+                    };
+                    self.resolve_function(method, function_type);
+                }
+
+                self.end_scope();
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDecl, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(&declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { .. } | Expr::Logical { .. } | Expr::Call { .. } | Expr::Get { .. } => {
+                // walked down to where the chain starts, then resolved left to right
+                let mut links = Vec::new();
+                let mut first = expr;
+                while let Some(inner) = first.chained() {
+                    links.push(first);
+                    first = inner;
+                }
+                self.resolve_expr(first);
+                for link in links.into_iter().rev() {
+                    self.resolve_link(link);
+                }
+            }
+            Expr::Grouping { expr, .. } | Expr::Unary { expr, .. } => self.resolve_expr(expr),
+            Expr::Literal { .. } => (),
+            Expr::Ternary {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_expr(then);
+                self.resolve_expr(otherwise);
+            }
+            Expr::Variable { name, depth, .. } => {
+                let lexeme = name.get_token().get_lexeme();
+                if let Some(false) = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(&lexeme))
+                    .map(|binding| binding.defined)
+                {
+                    self.error(name, "can't read local variable in its own initializer");
+                }
+                self.resolve_local(name, depth);
+            }
+            Expr::Assign {
+                name, value, depth, ..
+            } => {
+                self.resolve_expr(value);
+                self.resolve_local(name, depth);
+            }
+            Expr::Set { object, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::This { keyword, depth, .. } => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "can't use 'this' outside of a class");
+                    return;
+                }
+                self.resolve_local(keyword, depth);
+            }
+            Expr::Super { keyword, depth, .. } => match self.current_class {
+                ClassType::None => self.error(keyword, "can't use 'super' outside of a class"),
+                ClassType::Class => {
+                    self.error(keyword, "can't use 'super' in a class with no superclass")
+                }
+                ClassType::Subclass => self.resolve_local(keyword, depth),
+            },
+        }
+    }
+
+    // everything in one link of a chain but what it applies to
+    fn resolve_link(&mut self, link: &Expr) {
+        match link {
+            Expr::Binary { right, .. } | Expr::Logical { right, .. } => self.resolve_expr(right),
+            Expr::Call { arguments, .. } => {
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            _ => (),
+        }
+    }
+}
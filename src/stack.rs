@@ -0,0 +1,39 @@
+use std::hint;
+
+// how many bytes of native stack the parser and the interpreter may each use
+// below the frame they were started from. this leaves half a mebibyte of a
+// 2 MiB stack, the default for spawned threads, for the host's own frames and
+// for whatever runs between two checks, such as a host function
+pub const DEFAULT_STACK_LIMIT: usize = 1536 * 1024;
+
+// measures how far the native stack has grown since it was made, so recursion
+// driven by the script is stopped before it can overflow
+#[derive(Debug, Clone, Copy)]
+pub struct StackGuard {
+    base: usize,
+    limit: usize,
+}
+
+impl StackGuard {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            base: position(),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.base.abs_diff(position()) > self.limit
+    }
+}
+
+// the address of a local in the caller's frame, which moves with the stack
+#[inline(never)]
+fn position() -> usize {
+    let marker = 0u8;
+    hint::black_box(&marker) as *const u8 as usize
+}
@@ -1,3 +1,4 @@
+use crate::diagnostic::{Diagnostic, Phase, Span};
 use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -101,58 +102,90 @@ impl Token {
 #[derive(Debug, Clone)]
 pub struct CtxToken {
     token: Token,
-    line: usize,
-    column: usize,
+    span: Span,
 }
 
 impl CtxToken {
-    fn new(token: Token, line: usize, column: usize) -> Self {
-        Self {
-            token: token,
-            line: line,
-            column: column,
-        }
+    fn new(token: Token, span: Span) -> Self {
+        Self { token, span }
     }
 
     pub fn get_token(&self) -> Token {
         self.token.clone()
     }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
 impl fmt::Display for CtxToken {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?} [{}:{}]", self.token, self.line, self.column)
+        write!(f, "{:?} {}", self.token, self.span)
     }
 }
 
 pub struct Scanner {
     chars: Vec<char>,
+    // byte offset of each char, plus one past the end of the source
+    offsets: Vec<usize>,
     start: usize,
     current: usize,
     line: usize,
     column: usize,
-    has_error: bool,
+    start_line: usize,
+    start_column: usize,
+    // stamped on every span so diagnostics can be traced back to this source
+    source: usize,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
+        let offsets = source
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(std::iter::once(source.len()))
+            .collect();
+
         Self {
             chars: source.chars().collect(),
+            offsets,
             start: 0,
             current: 0,
             line: 1,
             column: 1,
-            has_error: false,
+            start_line: 1,
+            start_column: 1,
+            source: 0,
+            diagnostics: Vec::new(),
         }
     }
 
-    fn report(&self, message: &str) {
-        eprintln!("ERROR LEXER [{0}:{1}]: {message}", self.line, self.column);
+    pub fn with_source(mut self, source: usize) -> Self {
+        self.source = source;
+        self
+    }
+
+    // the span of the lexeme scanned so far
+    fn span(&self) -> Span {
+        let end = (self.current + 1).min(self.chars.len());
+        Span::new(
+            self.offsets[self.start],
+            self.offsets[end],
+            self.start_line,
+            self.start_column,
+        )
+        .with_source(self.source)
+    }
+
+    fn diagnostic(&self, message: &str) -> Diagnostic {
+        Diagnostic::error(Phase::Lexer, message, self.span())
     }
 
     fn error(&mut self, message: &str) {
-        self.has_error = true;
-        self.report(message);
+        let diagnostic = self.diagnostic(message);
+        self.diagnostics.push(diagnostic);
     }
 
     fn get_current(&self) -> Option<char> {
@@ -165,9 +198,10 @@ impl Scanner {
         self.chars.get(self.current).copied()
     }
 
+    // the following advance moves onto the first column of the new line
     fn advance_line(&mut self) {
         self.line += 1;
-        self.column = 1;
+        self.column = 0;
     }
 
     fn peek(&self) -> Option<char> {
@@ -213,7 +247,7 @@ impl Scanner {
         }
     }
 
-    pub fn scan(&mut self) -> Result<Vec<CtxToken>, ()> {
+    pub fn scan(&mut self) -> Result<Vec<CtxToken>, Vec<Diagnostic>> {
         let mut tokens: Vec<CtxToken> = Vec::new();
 
         while let Some(c) = self.get_current() {
@@ -286,7 +320,10 @@ impl Scanner {
                 '"' => loop {
                     match self.advance() {
                         None => {
-                            self.error("unterminated string");
+                            let diagnostic = self.diagnostic("unterminated string").with_note(
+                                "strings must be closed with '\"' before the end of the file",
+                            );
+                            self.diagnostics.push(diagnostic);
                             break None;
                         }
                         Some('"') => {
@@ -341,7 +378,7 @@ impl Scanner {
                     }
 
                     let literal: String = self.chars[self.start..=self.current].iter().collect();
-                    Scanner::lookup_keyword(&literal).or_else(|| Some(Token::Identifier(literal)))
+                    Scanner::lookup_keyword(&literal).or(Some(Token::Identifier(literal)))
                 }
                 _ => {
                     self.error("unexpected character");
@@ -349,17 +386,23 @@ impl Scanner {
                 }
             };
             if let Some(token) = token {
-                tokens.push(CtxToken::new(token, self.line, self.column));
+                tokens.push(CtxToken::new(token, self.span()));
             }
             let _ = self.advance();
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
         }
-        tokens.push(CtxToken::new(Token::Eof, self.line, self.column));
+        let end = self.offsets[self.chars.len()];
+        tokens.push(CtxToken::new(
+            Token::Eof,
+            Span::new(end, end, self.line, self.column).with_source(self.source),
+        ));
 
-        if self.has_error {
-            Err(())
-        } else {
+        if self.diagnostics.is_empty() {
             Ok(tokens)
+        } else {
+            Err(std::mem::take(&mut self.diagnostics))
         }
     }
 }
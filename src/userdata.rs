@@ -0,0 +1,68 @@
+use crate::native::{Args, NativeFunction};
+use crate::parser::Type;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// a host object handed to scripts, exposing named properties and methods
+// through `.` access
+pub trait UserData {
+    fn type_name(&self) -> &str;
+
+    fn get(&self, _name: &str) -> Option<Type> {
+        None
+    }
+
+    fn set(&mut self, name: &str, _value: Type) -> Result<(), String> {
+        Err(format!(
+            "can't set property '{}' on {}",
+            name,
+            self.type_name()
+        ))
+    }
+
+    // the number of arguments a method takes, or None if there is no such method
+    fn method_arity(&self, _name: &str) -> Option<usize> {
+        None
+    }
+
+    fn call_method(&mut self, name: &str, _args: &Args) -> Result<Type, String> {
+        Err(format!("{} has no method '{}'", self.type_name(), name))
+    }
+}
+
+impl fmt::Debug for dyn UserData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserData")
+            .field("type_name", &self.type_name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for dyn UserData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} userdata", self.type_name())
+    }
+}
+
+impl Type {
+    pub fn userdata<T: UserData + 'static>(data: T) -> Type {
+        Type::Userdata(Rc::new(RefCell::new(data)))
+    }
+}
+
+// properties take precedence over methods, which are bound to the object
+pub fn get(data: &Rc<RefCell<dyn UserData>>, name: &str) -> Option<Type> {
+    if let Some(value) = data.borrow().get(name) {
+        return Some(value);
+    }
+
+    let arity = data.borrow().method_arity(name)?;
+    let receiver = Rc::clone(data);
+    let method = name.to_string();
+    let bound = NativeFunction::new(name, arity, move |args| {
+        receiver.borrow_mut().call_method(&method, args)
+    });
+
+    Some(Type::Native(Rc::new(bound)))
+}
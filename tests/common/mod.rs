@@ -0,0 +1,34 @@
+#![allow(dead_code)]
+
+use lox::{CallbackWriter, Lox};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// a session that prints nothing
+pub fn quiet() -> Lox {
+    let mut lox = Lox::new();
+    lox.set_stdout(Vec::new());
+    lox.set_stderr(Vec::new());
+    lox
+}
+
+// a session whose printed output is collected into the returned buffer
+pub fn capturing() -> (Lox, Rc<RefCell<String>>) {
+    let output = Rc::new(RefCell::new(String::new()));
+    let sink = Rc::clone(&output);
+    let mut lox = quiet();
+    lox.set_stdout(CallbackWriter::new(move |text| {
+        sink.borrow_mut().push_str(text)
+    }));
+    (lox, output)
+}
+
+// what running `source` in a fresh session prints
+pub fn output(source: &str) -> String {
+    let (mut lox, output) = capturing();
+    if let Err(error) = lox.eval(source) {
+        panic!("{:?} failed: {}", source, error);
+    }
+    let printed = output.borrow().clone();
+    printed
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use common::quiet;
+use lox::{Diagnostic, LoxError, Phase, Span};
+
+// the line and column of each error `source` is rejected with
+fn syntax_errors(source: &str) -> Vec<(usize, usize)> {
+    match quiet().eval(source) {
+        Err(LoxError::Compile(diagnostics)) => diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.span.column))
+            .collect(),
+        other => panic!(
+            "expected a compile error from {:?}, got {:?}",
+            source, other
+        ),
+    }
+}
+
+#[test]
+fn errors_report_the_phase_they_come_from() {
+    for (source, phase) in [
+        ("@", Phase::Lexer),
+        ("print ;", Phase::Parser),
+        ("return 1;", Phase::Resolver),
+        ("nil + 1;", Phase::Runtime),
+    ] {
+        let error = quiet().eval(source).unwrap_err();
+        assert_eq!(error.diagnostics()[0].phase, phase, "{}", source);
+    }
+}
+
+#[test]
+fn diagnostics_serialise_to_json() {
+    let diagnostic = Diagnostic::error(Phase::Parser, "bad \"name\"", Span::new(4, 6, 2, 3))
+        .with_label(Span::new(0, 1, 1, 1), "opened\there")
+        .with_note("a\\b\nc\u{1}");
+    assert_eq!(
+        diagnostic.to_json(),
+        concat!(
+            r#"{"phase":"parser","severity":"error","message":"bad \"name\"","#,
+            r#""start":4,"end":6,"line":2,"column":3,"#,
+            r#""labels":[{"message":"opened\there","start":0,"end":1,"line":1,"column":1}],"#,
+            r#""trace":[],"notes":["a\\b\nc\u0001"]}"#
+        )
+    );
+}
+
+#[test]
+fn runtime_errors_serialise_their_trace() {
+    let error = quiet()
+        .eval("fun f() { return nil + 1; }\nf();")
+        .unwrap_err();
+    let json = error.diagnostics()[0].to_json();
+    assert!(
+        json.contains(r#""trace":[{"function":"f","line":2,"column":1}]"#),
+        "{}",
+        json
+    );
+}
+
+#[test]
+fn every_syntax_error_is_reported() {
+    let error = quiet()
+        .eval("var = 1;\nfun f() { var x = ; print 1; }\nprint ;\nprint 2")
+        .unwrap_err();
+    let LoxError::Compile(diagnostics) = error else {
+        panic!("expected a compile error, got {:?}", error);
+    };
+    let lines: Vec<usize> = diagnostics.iter().map(|d| d.span.line).collect();
+    assert_eq!(lines, [1, 2, 3, 4]);
+    assert!(diagnostics.iter().all(|d| d.phase == Phase::Parser));
+}
+
+#[test]
+fn unclosed_blocks_are_reported_once() {
+    assert_eq!(syntax_errors("}\n}"), [(1, 1), (2, 1)]);
+    assert_eq!(syntax_errors("print 1 }\nprint 2;"), [(1, 9)]);
+    assert_eq!(syntax_errors("if (x {\n  print 1;\n}\nprint 2;"), [(1, 7)]);
+    assert_eq!(syntax_errors("{\n  print 1;\n"), [(3, 1)]);
+    assert_eq!(syntax_errors("fun f() {\n  print 1\n}\nprint 2;"), [(3, 1)]);
+}
+
+#[test]
+fn spans_point_at_the_offending_code() {
+    let error = quiet().eval("var a = 1;\nprint a + nil;").unwrap_err();
+    let diagnostic = &error.diagnostics()[0];
+    assert_eq!(diagnostic.phase, Phase::Runtime);
+    assert_eq!((diagnostic.span.line, diagnostic.span.column), (2, 9));
+    assert_eq!((diagnostic.span.start, diagnostic.span.end), (19, 20));
+}
+
+#[test]
+fn rendering_shows_the_source_line() {
+    let mut lox = quiet();
+    let error = lox
+        .eval_named("main.lox", "var a;\nprint a + 1;")
+        .unwrap_err();
+    let rendered = lox.render(&error.diagnostics()[0]);
+    assert!(rendered.starts_with("error[runtime]: "), "{}", rendered);
+    assert!(rendered.contains("--> main.lox:2:9"), "{}", rendered);
+    assert!(rendered.contains("2 | print a + 1;"), "{}", rendered);
+    assert!(!rendered.contains('\x1b'), "{}", rendered);
+}
+
+#[test]
+fn errors_in_earlier_code_are_rendered_against_it() {
+    let mut lox = quiet();
+    lox.eval_named("lib.lox", "fun fail() {\n  return nil + 1;\n}")
+        .unwrap();
+    for _ in 0..3 {
+        lox.eval("1;").unwrap();
+    }
+    let error = lox.eval_named("main.lox", "fail();").unwrap_err();
+    let rendered = lox.render(&error.diagnostics()[0]);
+    assert!(rendered.contains("--> lib.lox:2:"), "{}", rendered);
+    assert!(rendered.contains("called at main.lox:1:"), "{}", rendered);
+}
+
+#[test]
+fn runtime_errors_carry_the_call_stack() {
+    let error = quiet()
+        .eval("fun inner() { return nil + 1; }\nfun outer() { return inner(); }\nouter();")
+        .unwrap_err();
+    let diagnostic = &error.diagnostics()[0];
+    let functions: Vec<&str> = diagnostic
+        .trace
+        .iter()
+        .map(|frame| frame.function.as_str())
+        .collect();
+    assert_eq!(functions, ["inner", "outer"]);
+    assert_eq!(diagnostic.trace[1].call_site.line, 3);
+}
+
+#[test]
+fn long_call_stacks_are_elided() {
+    let mut lox = quiet();
+    lox.set_trace_limit(4);
+    let error = lox
+        .eval("fun f(n) { if (n == 0) return nil + 1; return f(n - 1); } f(10);")
+        .unwrap_err();
+    let diagnostic = &error.diagnostics()[0];
+    assert_eq!(diagnostic.trace.len(), 4);
+    assert_eq!(diagnostic.notes, ["7 more calls not shown"]);
+}
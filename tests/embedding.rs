@@ -0,0 +1,142 @@
+mod common;
+
+use common::{capturing, quiet};
+use lox::{Args, CallbackWriter, FromLox, IntoLox, LoxError, UserData, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn eval_returns_the_last_expression() {
+    let mut lox = quiet();
+    let value = lox.eval("var x = 20; x + 1;").unwrap();
+    assert_eq!(f64::from_lox(&value), Ok(21.));
+    // globals carry over between calls
+    assert_eq!(f64::from_lox(&lox.eval("x;").unwrap()), Ok(20.));
+    assert!(matches!(lox.eval("print x;"), Ok(Value::Nil)));
+}
+
+#[test]
+fn errors_are_returned_by_phase() {
+    let mut lox = quiet();
+    assert!(matches!(lox.eval("print ;"), Err(LoxError::Compile(_))));
+    assert!(matches!(lox.eval("nil + 1;"), Err(LoxError::Runtime(_))));
+    assert!(matches!(
+        lox.run_file("tests/missing.lox"),
+        Err(LoxError::Io(_))
+    ));
+}
+
+#[test]
+fn host_functions_are_callable_from_scripts() {
+    let (mut lox, output) = capturing();
+    lox.register_fn("twice", 1, |args: &Args| {
+        Ok((args.number(0)? * 2.).into_lox())
+    });
+    lox.eval("print twice(21);").unwrap();
+    assert_eq!(*output.borrow(), "42\n");
+
+    let error = lox.eval("twice(1, 2);").unwrap_err();
+    assert!(matches!(error, LoxError::Runtime(_)));
+}
+
+#[test]
+fn host_function_errors_are_raised_at_the_call_site() {
+    let mut lox = quiet();
+    lox.register_fn("twice", 1, |args: &Args| {
+        Ok((args.number(0)? * 2.).into_lox())
+    });
+    let error = lox.eval("twice(1);\ntwice(\"a\");").unwrap_err();
+    let diagnostic = &error.diagnostics()[0];
+    assert!(matches!(error, LoxError::Runtime(_)));
+    assert_eq!(diagnostic.span.line, 2);
+}
+
+#[test]
+fn typed_host_functions_convert_their_arguments() {
+    let (mut lox, output) = capturing();
+    lox.register_typed("greet", |name: String, times: u8| {
+        name.repeat(times as usize)
+    });
+    lox.eval("print greet(\"hi\", 3);").unwrap();
+    assert_eq!(*output.borrow(), "hihihi\n");
+
+    let error = lox.eval("greet(\"hi\", 256);").unwrap_err();
+    assert!(matches!(error, LoxError::Runtime(_)));
+}
+
+#[test]
+fn integers_outside_the_target_type_are_rejected() {
+    assert_eq!(u8::from_lox(&255.into_lox()), Ok(255));
+    assert!(u8::from_lox(&256.into_lox()).is_err());
+    assert!(u8::from_lox(&(-1).into_lox()).is_err());
+    assert!(i32::from_lox(&1.5.into_lox()).is_err());
+    assert!(u64::from_lox(&18446744073709551616f64.into_lox()).is_err());
+    assert!(i64::from_lox(&9223372036854775808f64.into_lox()).is_err());
+    assert_eq!(
+        i64::from_lox(&(-9223372036854775808f64).into_lox()),
+        Ok(i64::MIN)
+    );
+    assert!(f64::from_lox(&"1".into_lox()).is_err());
+    assert_eq!(Option::<f64>::from_lox(&Value::Nil), Ok(None));
+}
+
+struct Counter {
+    count: f64,
+}
+
+impl UserData for Counter {
+    fn type_name(&self) -> &str {
+        "Counter"
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        (name == "count").then(|| self.count.into_lox())
+    }
+
+    fn method_arity(&self, name: &str) -> Option<usize> {
+        (name == "add").then_some(1)
+    }
+
+    fn call_method(&mut self, _name: &str, args: &Args) -> Result<Value, String> {
+        self.count += args.number(0)?;
+        Ok(Value::Nil)
+    }
+}
+
+#[test]
+fn userdata_exposes_properties_and_methods() {
+    let (mut lox, output) = capturing();
+    lox.set_global("counter", Value::userdata(Counter { count: 1. }));
+    lox.set_global("other", Value::userdata(Counter { count: 1. }));
+    lox.eval(
+        "counter.add(2); counter.add(3); print counter.count;
+        print counter; print counter == counter; print counter == other;",
+    )
+    .unwrap();
+    assert_eq!(*output.borrow(), "6\nCounter userdata\ntrue\nfalse\n");
+
+    let error = lox.eval("counter.count = 1;").unwrap_err();
+    assert!(matches!(error, LoxError::Runtime(_)));
+}
+
+#[test]
+fn output_and_errors_go_to_the_configured_sinks() {
+    let (mut lox, output) = capturing();
+    let errors = Rc::new(RefCell::new(String::new()));
+    let sink = Rc::clone(&errors);
+    lox.set_stderr(CallbackWriter::new(move |text| {
+        sink.borrow_mut().push_str(text)
+    }));
+
+    lox.eval("print 1 + 2; print \"a\" + \"b\";").unwrap();
+    assert_eq!(*output.borrow(), "3\nab\n");
+    assert_eq!(*errors.borrow(), "");
+
+    lox.eval("print nil + 1;").unwrap_err();
+    assert_eq!(*output.borrow(), "3\nab\n");
+    assert!(
+        errors.borrow().starts_with("error[runtime]"),
+        "{}",
+        errors.borrow()
+    );
+}
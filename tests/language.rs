@@ -0,0 +1,256 @@
+mod common;
+
+use common::{output, quiet};
+use lox::{LoxError, Phase};
+
+// the first diagnostic of a script that fails before it runs
+fn compile_error(source: &str) -> (Phase, String) {
+    match quiet().eval(source) {
+        Err(LoxError::Compile(diagnostics)) => {
+            (diagnostics[0].phase, diagnostics[0].message.clone())
+        }
+        other => panic!(
+            "expected a compile error from {:?}, got {:?}",
+            source, other
+        ),
+    }
+}
+
+// the message of a script's runtime error
+fn runtime_error(source: &str) -> String {
+    match quiet().eval(source) {
+        Err(LoxError::Runtime(diagnostic)) => diagnostic.message,
+        other => panic!(
+            "expected a runtime error from {:?}, got {:?}",
+            source, other
+        ),
+    }
+}
+
+#[test]
+fn statements_run_in_order() {
+    assert_eq!(
+        output("print 1 + 2; print \"a\"; 3; print true;"),
+        "3\na\ntrue\n"
+    );
+}
+
+#[test]
+fn a_statement_without_a_semicolon_is_a_syntax_error() {
+    assert_eq!(compile_error("print 1").0, Phase::Parser);
+}
+
+#[test]
+fn variables_are_declared_and_assigned() {
+    assert_eq!(
+        output("var a; print a; var b = 1; a = b = b + 1; print a; print b;"),
+        "nil\n2\n2\n"
+    );
+}
+
+#[test]
+fn undefined_variables_are_runtime_errors() {
+    assert!(runtime_error("print x;").contains('x'));
+    assert!(runtime_error("x = 1;").contains('x'));
+}
+
+#[test]
+fn only_variables_can_be_assigned_to() {
+    assert_eq!(compile_error("var a; a + 1 = 2;").0, Phase::Parser);
+}
+
+#[test]
+fn blocks_shadow_and_restore_variables() {
+    assert_eq!(
+        output("var a = 1; { var a = 2; { a = 3; print a; } print a; } print a;"),
+        "3\n3\n1\n"
+    );
+}
+
+#[test]
+fn control_flow_picks_branches_and_loops() {
+    assert_eq!(
+        output(
+            "if (1 > 2) print 1; else print 2;
+            if (nil) print 3;
+            var i = 0; while (i < 3) i = i + 1; print i;
+            for (var j = 0; j < 3; j = j + 1) print j;"
+        ),
+        "2\n3\n0\n1\n2\n"
+    );
+}
+
+#[test]
+fn a_for_loop_variable_is_scoped_to_the_loop() {
+    assert!(runtime_error("for (var j = 0; j < 1; j = j + 1) {} print j;").contains('j'));
+}
+
+#[test]
+fn logical_operators_short_circuit_to_an_operand() {
+    assert_eq!(
+        output(
+            "print nil or \"x\"; print 1 and 2; print false and undefined;
+            print 1 or undefined; print nil and 1;"
+        ),
+        "x\n2\nfalse\n1\nnil\n"
+    );
+}
+
+#[test]
+fn functions_return_values() {
+    assert_eq!(
+        output(
+            "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            fun nothing() {}
+            print fib(10); print nothing();"
+        ),
+        "55\nnil\n"
+    );
+}
+
+#[test]
+fn calls_check_the_callee_and_arity() {
+    runtime_error("fun f(a) {} f();");
+    runtime_error("\"f\"();");
+}
+
+#[test]
+fn closures_keep_their_own_variables() {
+    assert_eq!(
+        output(
+            "fun counter() { var n = 0; fun count() { n = n + 1; return n; } return count; }
+            var a = counter(); var b = counter();
+            a(); a(); print a(); print b();"
+        ),
+        "3\n1\n"
+    );
+}
+
+#[test]
+fn closures_see_later_assignments() {
+    assert_eq!(
+        output("var f; { var x = 1; fun get() { return x; } x = 2; f = get; } print f();"),
+        "2\n"
+    );
+}
+
+#[test]
+fn variables_bind_where_the_function_is_declared() {
+    assert_eq!(
+        output(
+            "var a = \"global\";
+            { fun show() { print a; } show(); var a = \"block\"; show(); }"
+        ),
+        "global\nglobal\n"
+    );
+}
+
+#[test]
+fn misused_names_are_resolver_errors() {
+    for (source, message) in [
+        ("return 1;", "can't return from top-level code"),
+        (
+            "{ var a = a; }",
+            "can't read local variable in its own initializer",
+        ),
+        (
+            "{ var a; var a; }",
+            "already a variable with this name in this scope",
+        ),
+        ("print this;", "can't use 'this' outside of a class"),
+        (
+            "fun f() { super.m(); }",
+            "can't use 'super' outside of a class",
+        ),
+        (
+            "class A { m() { super.m(); } }",
+            "can't use 'super' in a class with no superclass",
+        ),
+        ("class A < A {}", "a class can't inherit from itself"),
+    ] {
+        assert_eq!(
+            compile_error(source),
+            (Phase::Resolver, message.to_string()),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn methods_see_their_instance_through_this() {
+    assert_eq!(
+        output(
+            "class Point {
+                init(x) { this.x = x; }
+                get() { return this.x; }
+            }
+            var p = Point(1); p.x = 2;
+            var get = p.get; print get();
+            p.y = 3; print p.y;"
+        ),
+        "2\n3\n"
+    );
+}
+
+#[test]
+fn init_returns_the_instance() {
+    assert_eq!(
+        output(
+            "class A { init() { this.n = 1; return; } }
+            var a = A(); print a.init() == a; print a.n;"
+        ),
+        "true\n1\n"
+    );
+    assert_eq!(
+        compile_error("class A { init() { return 1; } }"),
+        (
+            Phase::Resolver,
+            "can't return a value from an initializer".to_string()
+        )
+    );
+}
+
+#[test]
+fn missing_properties_are_runtime_errors() {
+    runtime_error("class A {} A().x;");
+    runtime_error("var a = 1; a.x = 2;");
+}
+
+#[test]
+fn super_calls_the_superclass_of_the_declaring_class() {
+    assert_eq!(
+        output(
+            "class A { name() { return \"A\"; } }
+            class B < A { name() { return \"B\"; } test() { return super.name(); } }
+            class C < B { name() { return \"C\"; } }
+            print C().test(); print C().name();"
+        ),
+        "A\nC\n"
+    );
+}
+
+#[test]
+fn subclasses_inherit_methods_and_init() {
+    assert_eq!(
+        output(
+            "class A { init(n) { this.n = n; } double() { return this.n * 2; } }
+            class B < A {}
+            print B(4).double();"
+        ),
+        "8\n"
+    );
+    runtime_error("var A = 1; class B < A {}");
+}
+
+#[test]
+fn native_functions_are_predefined() {
+    assert_eq!(
+        output(
+            "print str(1) + \"!\"; print num(\"2.5\") * 2;
+            print len(\"abc\"); print type(nil); print type(clock());"
+        ),
+        "1!\n5\n3\nnil\nnumber\n"
+    );
+    runtime_error("num(\"x\");");
+}
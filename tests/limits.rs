@@ -0,0 +1,262 @@
+mod common;
+
+use common::quiet;
+use lox::{Abort, FromLox, LoxError, Value};
+use std::thread;
+use std::time::Duration;
+
+fn message(error: &LoxError) -> &str {
+    &error.diagnostics()[0].message
+}
+
+fn number(result: Result<Value, LoxError>) -> f64 {
+    f64::from_lox(&result.unwrap()).unwrap()
+}
+
+#[test]
+fn unbounded_recursion_is_a_runtime_error() {
+    for source in [
+        "fun f(n) { return f(n + 1); } f(0);",
+        "class A { m(n) { return this.m(n + 1); } } A().m(0);",
+    ] {
+        let error = quiet().eval(source).unwrap_err();
+        assert!(matches!(error, LoxError::Runtime(_)), "{}", source);
+        assert_eq!(message(&error), "stack overflow");
+    }
+}
+
+#[test]
+fn recursion_through_nested_blocks_is_a_runtime_error() {
+    let blocks = format!("{}{}", "{".repeat(20), "}".repeat(20));
+    let source = format!(
+        "fun f(n) {{ if (n > 0) {{ {} return f(n - 1); }} return 0; }} f(100000);",
+        blocks
+    );
+    let error = quiet().eval(&source).unwrap_err();
+    assert!(matches!(error, LoxError::Runtime(_)));
+    assert_eq!(message(&error), "stack overflow");
+}
+
+#[test]
+fn recursion_within_the_limit_runs() {
+    let mut lox = quiet();
+    let value = lox.eval("fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); } f(200);");
+    assert_eq!(number(value), 200.);
+    let value = lox
+        .eval("class A { m(n) { if (n == 0) return 0; return 1 + this.m(n - 1); } } A().m(200);");
+    assert_eq!(number(value), 200.);
+}
+
+#[test]
+fn a_session_recovers_after_a_stack_overflow() {
+    let mut lox = quiet();
+    lox.eval("fun f() { return f(); } f();").unwrap_err();
+    assert_eq!(number(lox.eval("1 + 2;")), 3.);
+}
+
+#[test]
+fn deep_nesting_is_a_compile_error() {
+    let sources = [
+        format!("{}1{};", "(".repeat(100_000), ")".repeat(100_000)),
+        format!("{}1;", "-".repeat(100_000)),
+        format!("{}{}", "{".repeat(100_000), "}".repeat(100_000)),
+    ];
+    for source in sources {
+        let error = quiet().eval(&source).unwrap_err();
+        assert!(matches!(error, LoxError::Compile(_)));
+        assert_eq!(message(&error), "stack overflow");
+    }
+}
+
+#[test]
+fn long_chains_run_without_nesting() {
+    let mut lox = quiet();
+    let sum = format!("{};", vec!["1"; 100_000].join(" + "));
+    assert_eq!(number(lox.eval(&sum)), 100_000.);
+    let calls = format!("fun f() {{ return f; }} f{};", "()".repeat(100_000));
+    lox.eval(&calls).unwrap();
+    let logic = format!("{} or 1;", vec!["false"; 100_000].join(" or "));
+    assert_eq!(number(lox.eval(&logic)), 1.);
+    let properties = format!(
+        "class A {{}} var a = A(); a.a = a; a{}.a = 1;",
+        ".a".repeat(100_000)
+    );
+    lox.eval(&properties).unwrap();
+}
+
+#[test]
+fn nesting_limit_of_zero_rejects_everything() {
+    let mut lox = quiet();
+    lox.set_max_nesting(0);
+    let error = lox.eval("print 1; print 2;").unwrap_err();
+    assert!(matches!(error, LoxError::Compile(_)));
+}
+
+#[test]
+fn depth_and_stack_limits_can_be_lowered() {
+    let mut lox = quiet();
+    lox.set_max_depth(50);
+    let error = lox
+        .eval("fun f(n) { if (n > 0) f(n - 1); } f(100);")
+        .unwrap_err();
+    assert_eq!(
+        error.diagnostics()[0].notes[0],
+        "evaluation is limited to 50 levels of nesting"
+    );
+
+    let mut lox = quiet();
+    lox.set_stack_limit(64 * 1024);
+    let error = lox
+        .eval("fun f(n) { if (n > 0) f(n - 1); } f(100000);")
+        .unwrap_err();
+    assert_eq!(message(&error), "stack overflow");
+    assert_eq!(
+        error.diagnostics()[0].notes[0],
+        "evaluation is limited to 64 KiB of native stack"
+    );
+    let error = lox
+        .eval(&format!("{}1{};", "(".repeat(100), ")".repeat(100)))
+        .unwrap_err();
+    assert_eq!(
+        error.diagnostics()[0].notes,
+        ["parsing is limited to 64 KiB of native stack"]
+    );
+}
+
+#[test]
+fn long_chains_of_objects_are_dropped() {
+    let mut lox = quiet();
+    lox.eval(
+        "class Node { init(next) { this.next = next; } }
+        var list = nil;
+        for (var i = 0; i < 50000; i = i + 1) list = Node(list);
+        list = nil;
+
+        fun wrap(f) { fun g() { return f; } return g; }
+        var f = nil;
+        for (var i = 0; i < 50000; i = i + 1) f = wrap(f);
+        f = nil;
+
+        var c = Node;
+        for (var i = 0; i < 50000; i = i + 1) { class A < c {} c = A; }
+        c = nil;",
+    )
+    .unwrap();
+}
+
+#[test]
+fn running_out_of_fuel_aborts() {
+    let mut lox = quiet();
+    lox.set_fuel(Some(100));
+    let error = lox.eval("while (true) {}").unwrap_err();
+    assert!(matches!(error, LoxError::Aborted(Abort::OutOfFuel, _)));
+    assert_eq!(lox.fuel(), Some(0));
+    assert_eq!(lox.fuel_used(), 100);
+}
+
+#[test]
+fn added_fuel_lets_later_calls_run() {
+    let mut lox = quiet();
+    lox.set_fuel(Some(10));
+    lox.eval("while (true) {}").unwrap_err();
+    lox.add_fuel(10);
+    assert_eq!(number(lox.eval("1 + 2;")), 3.);
+    assert_eq!(lox.fuel(), Some(7));
+}
+
+#[test]
+fn refuelling_resumes_the_script() {
+    let mut lox = quiet();
+    lox.set_fuel(Some(100));
+    let mut refills = 0;
+    lox.set_refuel(move |_| {
+        refills += 1;
+        (refills <= 10).then_some(100)
+    });
+    let value = lox.eval("var i = 0; while (i < 100) i = i + 1; i;");
+    assert_eq!(number(value), 100.);
+
+    let error = lox.eval("while (true) {}").unwrap_err();
+    assert!(matches!(error, LoxError::Aborted(Abort::OutOfFuel, _)));
+    assert_eq!(lox.fuel_used(), 1100);
+}
+
+#[test]
+fn a_long_call_times_out() {
+    let mut lox = quiet();
+    lox.set_timeout(Some(Duration::from_millis(50)));
+    let error = lox.eval("while (true) {}").unwrap_err();
+    assert!(matches!(error, LoxError::Aborted(Abort::Timeout, _)));
+    assert_eq!(number(lox.eval("1 + 2;")), 3.);
+}
+
+#[test]
+fn another_thread_can_interrupt_a_script() {
+    let mut lox = quiet();
+    let handle = lox.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.interrupt();
+    });
+    let error = lox.eval("while (true) {}").unwrap_err();
+    interrupter.join().unwrap();
+    assert!(matches!(error, LoxError::Aborted(Abort::Interrupted, _)));
+    assert_eq!(number(lox.eval("1 + 2;")), 3.);
+}
+
+#[test]
+fn growing_past_the_memory_limit_aborts() {
+    let mut lox = quiet();
+    lox.set_memory_limit(Some(lox.memory_used() + 10_000));
+    let error = lox
+        .eval("var s = \"x\"; while (true) s = s + s;")
+        .unwrap_err();
+    assert!(matches!(error, LoxError::Aborted(Abort::OutOfMemory, _)));
+    assert!(lox.peak_memory_used() <= lox.memory_used().max(10_000) + 10_000);
+}
+
+#[test]
+fn memory_is_released_when_values_are_dropped() {
+    let mut lox = quiet();
+    let before = lox.memory_used();
+    lox.eval("{ var s = \"a\" + \"b\"; class A {} var a = A(); a.s = s; }")
+        .unwrap();
+    assert_eq!(lox.memory_used(), before);
+}
+
+#[test]
+fn unreachable_cycles_are_collected_within_the_limit() {
+    let mut lox = quiet();
+    lox.eval(
+        "class Node {}
+        fun outer() { fun inner() {} }
+        fun make() { var n = 0; fun count() { n = n + 1; return n; } return count; }
+        fun local() { class A {} class B < A { m() { return super.m; } } return B; }
+        fun cycle() { var a = Node(); a.self = a; var b = Node(); b.f = fun_of(b); }
+        fun fun_of(x) { fun g() { return x; } return g; }
+        fun run() { outer(); var count = make(); count(); local(); cycle(); }",
+    )
+    .unwrap();
+    lox.eval("run();").unwrap();
+
+    lox.set_memory_limit(Some(lox.memory_used() + 100_000));
+    lox.eval("for (var i = 0; i < 20000; i = i + 1) run();")
+        .unwrap();
+}
+
+#[test]
+fn collecting_is_charged_to_fuel() {
+    let mut lox = quiet();
+    lox.eval("class Node {} var keep = nil;").unwrap();
+    lox.set_fuel(Some(u64::MAX));
+    lox.eval("for (var i = 0; i < 3000; i = i + 1) { var a = Node(); a.next = keep; keep = a; }")
+        .unwrap();
+    let kept = lox.fuel_used();
+
+    let mut lox = quiet();
+    lox.eval("class Node {} var keep = nil;").unwrap();
+    lox.set_fuel(Some(u64::MAX));
+    lox.eval("for (var i = 0; i < 3000; i = i + 1) { var a = Node(); a.next = nil; keep = nil; }")
+        .unwrap();
+    assert!(kept > lox.fuel_used(), "{} <= {}", kept, lox.fuel_used());
+}